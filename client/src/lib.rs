@@ -0,0 +1,224 @@
+// A client for the exchange rate tracker, for off-chain Rust services.
+//
+// It derives the tracker's PDAs, decodes `RateData` accounts with their
+// discriminator checked, and builds the instructions a feed operator needs, so
+// services never copy the program's seeds or struct definitions. Nothing here
+// talks to a cluster: fetch accounts and send transactions with whichever RPC
+// client the service already uses.
+
+use anchor_lang::{system_program, Discriminator, InstructionData, ToAccountMetas};
+use fiat_crypto_tracker::{accounts, instruction};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+pub use fiat_crypto_tracker::{
+    CurrencyPair, Oracle, RateData, ID, PAIR_SEPARATOR, RATE_DATA_SEED, RATE_HISTORY_SEED,
+};
+
+// The pair's `rate_data` PDA and its bump.
+pub fn rate_data_pda(pair: &CurrencyPair) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        &ID,
+    )
+}
+
+// The price history PDA of the feed at `rate_data`, and its bump.
+pub fn rate_history_pda(rate_data: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RATE_HISTORY_SEED, rate_data.as_ref()], &ID)
+}
+
+// Why an account could not be decoded as `RateData`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    // The account is not owned by the tracker program.
+    WrongOwner,
+    // The account does not start with the `RateData` discriminator.
+    DiscriminatorMismatch,
+    // The account is a feed still in the layout used before `RateData` became
+    // zero-copy. Its authority needs to run `migrate_rate_data`.
+    LegacyLayout,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DecodeError::WrongOwner => "account is not owned by the tracker program",
+            DecodeError::DiscriminatorMismatch => "account is not a RateData account",
+            DecodeError::LegacyLayout => "RateData account has not been migrated",
+        })
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// Decodes a `RateData` account's data, checking its discriminator and layout.
+pub fn decode_rate_data(data: &[u8]) -> Result<RateData, DecodeError> {
+    if data.len() < 8 || &data[..8] != RateData::DISCRIMINATOR {
+        return Err(DecodeError::DiscriminatorMismatch);
+    }
+    // Legacy feeds share the discriminator; only migrated ones are exactly `SPACE`.
+    if data.len() != RateData::SPACE {
+        return Err(DecodeError::LegacyLayout);
+    }
+    Ok(bytemuck::pod_read_unaligned(&data[8..]))
+}
+
+// Decodes a fetched `RateData` account, also checking it belongs to the tracker.
+pub fn decode_rate_data_account(account: &Account) -> Result<RateData, DecodeError> {
+    if account.owner != ID {
+        return Err(DecodeError::WrongOwner);
+    }
+    decode_rate_data(&account.data)
+}
+
+// The oracles registered on a `RateData` account, in the order they were added.
+pub fn decode_oracles(data: &[u8]) -> Result<Vec<Oracle>, DecodeError> {
+    Ok(decode_rate_data(data)?.active_oracles().to_vec())
+}
+
+// Creates the feed for `pair`, with `authority` paying for and managing it.
+pub fn initialize(authority: &Pubkey, pair: &CurrencyPair, exponent: u8) -> Instruction {
+    let (rate_data, _) = rate_data_pda(pair);
+    Instruction {
+        program_id: ID,
+        accounts: accounts::Initialize {
+            rate_data,
+            rate_history: rate_history_pda(&rate_data).0,
+            authority: *authority,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::Initialize { pair: pair.clone(), exponent }.data(),
+    }
+}
+
+// Registers `oracle` on the feed for `pair`. Feeds with a council only accept
+// this through a proposal.
+pub fn add_oracle(
+    authority: &Pubkey,
+    pair: &CurrencyPair,
+    name: &str,
+    oracle: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: accounts::ManageOracle { rate_data: rate_data_pda(pair).0, authority: *authority }
+            .to_account_metas(None),
+        data: instruction::AddOracle {
+            _pair: pair.clone(),
+            name: name.to_string(),
+            oracle_pubkey: *oracle,
+        }
+        .data(),
+    }
+}
+
+// Submits `new_rate` for `pair`, signed by the registered `oracle`.
+pub fn update_rate(oracle: &Pubkey, pair: &CurrencyPair, new_rate: u64) -> Instruction {
+    let (rate_data, _) = rate_data_pda(pair);
+    Instruction {
+        program_id: ID,
+        accounts: accounts::UpdateRate {
+            rate_data,
+            rate_history: rate_history_pda(&rate_data).0,
+            oracle: *oracle,
+        }
+        .to_account_metas(None),
+        data: instruction::UpdateRate { pair: pair.clone(), new_rate }.data(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::AnchorDeserialize;
+    use bytemuck::Zeroable;
+
+    fn usd_ngn() -> CurrencyPair {
+        CurrencyPair { base: "USD".to_string(), quote: "NGN".to_string() }
+    }
+
+    fn rate_data_bytes(rate_data: &RateData) -> Vec<u8> {
+        [RateData::DISCRIMINATOR, bytemuck::bytes_of(rate_data)].concat()
+    }
+
+    #[test]
+    fn derives_one_pda_per_pair() {
+        let (usd_ngn_pda, bump) = rate_data_pda(&usd_ngn());
+        let seeds: &[&[u8]] = &[RATE_DATA_SEED, b"USD", PAIR_SEPARATOR, b"NGN", &[bump]];
+        assert_eq!(Pubkey::create_program_address(seeds, &ID), Ok(usd_ngn_pda));
+
+        // The separator keeps codes that concatenate the same way apart.
+        let usdt_ngn = CurrencyPair { base: "USDT".to_string(), quote: "NGN".to_string() };
+        let usd_tngn = CurrencyPair { base: "USD".to_string(), quote: "TNGN".to_string() };
+        assert_ne!(rate_data_pda(&usdt_ngn).0, rate_data_pda(&usd_tngn).0);
+        assert_ne!(rate_history_pda(&usd_ngn_pda).0, usd_ngn_pda);
+    }
+
+    #[test]
+    fn decodes_rate_data_and_oracles() {
+        let mut rate_data = RateData::zeroed();
+        rate_data.aggregate_rate = 145_025;
+        rate_data.exponent = 2;
+        let oracle = Pubkey::new_unique();
+        rate_data
+            .push_oracle(Oracle { pubkey: oracle, rate: 145_025, weight: 1, ..Zeroable::zeroed() })
+            .unwrap();
+        let data = rate_data_bytes(&rate_data);
+
+        let decoded = decode_rate_data(&data).unwrap();
+        assert_eq!((decoded.aggregate_rate, decoded.exponent), (145_025, 2));
+        let oracles = decode_oracles(&data).unwrap();
+        assert_eq!(oracles.len(), 1);
+        assert_eq!((oracles[0].pubkey, oracles[0].rate), (oracle, 145_025));
+
+        let account = Account { data, owner: ID, ..Account::default() };
+        assert!(decode_rate_data_account(&account).is_ok());
+        let foreign = Account { owner: Pubkey::new_unique(), ..account };
+        assert_eq!(decode_rate_data_account(&foreign).err(), Some(DecodeError::WrongOwner));
+    }
+
+    #[test]
+    fn rejects_other_accounts() {
+        let mut data = rate_data_bytes(&RateData::zeroed());
+        assert_eq!(decode_rate_data(&data[..4]).err(), Some(DecodeError::DiscriminatorMismatch));
+
+        // A legacy feed has the right discriminator but not the zero-copy size.
+        assert_eq!(decode_rate_data(&data[..211]).err(), Some(DecodeError::LegacyLayout));
+
+        data[0] ^= 1;
+        assert_eq!(decode_rate_data(&data).err(), Some(DecodeError::DiscriminatorMismatch));
+    }
+
+    #[test]
+    fn builds_instructions() {
+        let (authority, oracle) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (rate_data, _) = rate_data_pda(&usd_ngn());
+        let (rate_history, _) = rate_history_pda(&rate_data);
+
+        let ix = initialize(&authority, &usd_ngn(), 2);
+        assert_eq!(ix.program_id, ID);
+        let keys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys, [rate_data, rate_history, authority, system_program::ID]);
+        assert!(ix.accounts[2].is_signer && ix.accounts[2].is_writable);
+        assert_eq!(&ix.data[..8], instruction::Initialize::DISCRIMINATOR);
+        let args = instruction::Initialize::try_from_slice(&ix.data[8..]).unwrap();
+        assert_eq!((args.pair.base.as_str(), args.exponent), ("USD", 2));
+
+        let ix = add_oracle(&authority, &usd_ngn(), "Binance", &oracle);
+        assert_eq!(ix.accounts[0].pubkey, rate_data);
+        assert!(ix.accounts[0].is_writable && ix.accounts[1].is_signer);
+        assert_eq!(&ix.data[..8], instruction::AddOracle::DISCRIMINATOR);
+        let args = instruction::AddOracle::try_from_slice(&ix.data[8..]).unwrap();
+        assert_eq!((args.name.as_str(), args.oracle_pubkey), ("Binance", oracle));
+
+        let ix = update_rate(&oracle, &usd_ngn(), 145_025);
+        let keys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys, [rate_data, rate_history, oracle]);
+        assert!(ix.accounts[2].is_signer && !ix.accounts[2].is_writable);
+        let args = instruction::UpdateRate::try_from_slice(&ix.data[8..]).unwrap();
+        assert_eq!(args.new_rate, 145_025);
+    }
+}
@@ -0,0 +1,230 @@
+// Decoding the tracker's events from their raw `Program data:` bytes.
+//
+// Each event starts with its 8-byte discriminator followed by its Borsh-encoded
+// fields, so the program crate's own event structs decode them. Events from a
+// newer program version that this indexer doesn't know are skipped.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use fiat_crypto_tracker::*;
+use serde_json::{json, Value};
+
+fn parse<T: Discriminator + AnchorDeserialize>(data: &[u8]) -> Option<T> {
+    let mut fields = data.strip_prefix(T::DISCRIMINATOR)?;
+    T::deserialize(&mut fields).ok()
+}
+
+macro_rules! tracker_events {
+    ($($event:ident),* $(,)?) => {
+        // Every event the tracker emits.
+        pub enum TrackerEvent {
+            $($event($event),)*
+        }
+
+        impl TrackerEvent {
+            pub fn decode(data: &[u8]) -> Option<Self> {
+                $(
+                    if let Some(event) = parse::<$event>(data) {
+                        return Some(TrackerEvent::$event(event));
+                    }
+                )*
+                None
+            }
+
+            // The event's name, e.g. "OracleAdded".
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(TrackerEvent::$event(_) => stringify!($event),)*
+                }
+            }
+
+            // The feed the event is about.
+            pub fn rate_data(&self) -> Pubkey {
+                match self {
+                    $(TrackerEvent::$event(event) => event.rate_data,)*
+                }
+            }
+        }
+    };
+}
+
+tracker_events!(
+    TrackerInitialized,
+    OracleAdded,
+    OracleRemoved,
+    OracleKeyReplaced,
+    SettingsChanged,
+    RateUpdated,
+    RateRejected,
+    HistoryResized,
+    RateDataMigrated,
+    AuthorityTransferProposed,
+    AuthorityTransferAccepted,
+    AuthorityTransferCancelled,
+    CouncilUpdated,
+    AdminActionProposed,
+    AdminActionApproved,
+    AdminActionExecuted,
+    AdminActionCancelled,
+    FeedStatusChanged,
+    OracleHaltChanged,
+    StakeDeposited,
+    UnstakeRequested,
+    StakeWithdrawn,
+    StakeSynced,
+    SlashApproved,
+    OracleSlashed,
+    RewardsFunded,
+    RewardsClaimed,
+    FeedDelegated,
+    FeedUndelegated,
+    CommitScheduled,
+);
+
+impl TrackerEvent {
+    // The event's fields other than `rate_data`, as stored with admin actions.
+    // Pubkeys are base58 and admin actions use their debug form.
+    pub fn details(&self) -> Value {
+        match self {
+            TrackerEvent::TrackerInitialized(e) => json!({
+                "authority": e.authority.to_string(),
+                "base": e.base,
+                "quote": e.quote,
+                "exponent": e.exponent,
+            }),
+            TrackerEvent::OracleAdded(e) => {
+                json!({ "oracle": e.oracle.to_string(), "name": e.name })
+            }
+            TrackerEvent::OracleRemoved(e) => json!({
+                "oracle": e.oracle.to_string(),
+                "forfeited_rewards": e.forfeited_rewards,
+            }),
+            TrackerEvent::OracleKeyReplaced(e) => json!({
+                "old_pubkey": e.old_pubkey.to_string(),
+                "new_pubkey": e.new_pubkey.to_string(),
+            }),
+            TrackerEvent::SettingsChanged(e) => json!({ "action": format!("{:?}", e.action) }),
+            TrackerEvent::RateUpdated(e) => json!({
+                "oracle": e.oracle.to_string(),
+                "old_rate": e.old_rate,
+                "new_rate": e.new_rate,
+                "change_bps": e.change_bps,
+                "aggregate_rate": e.aggregate_rate,
+                "num_contributors": e.num_contributors,
+                "timestamp": e.timestamp,
+            }),
+            TrackerEvent::RateRejected(e) => json!({
+                "oracle": e.oracle.to_string(),
+                "rate": e.rate,
+                "aggregate_rate": e.aggregate_rate,
+                "timestamp": e.timestamp,
+            }),
+            TrackerEvent::HistoryResized(e) => json!({ "capacity": e.capacity }),
+            TrackerEvent::RateDataMigrated(e) => json!({ "authority": e.authority.to_string() }),
+            TrackerEvent::AuthorityTransferProposed(e) => json!({
+                "authority": e.authority.to_string(),
+                "pending_authority": e.pending_authority.to_string(),
+            }),
+            TrackerEvent::AuthorityTransferAccepted(e) => json!({
+                "old_authority": e.old_authority.to_string(),
+                "new_authority": e.new_authority.to_string(),
+            }),
+            TrackerEvent::AuthorityTransferCancelled(e) => json!({
+                "authority": e.authority.to_string(),
+                "cancelled_authority": e.cancelled_authority.to_string(),
+            }),
+            TrackerEvent::CouncilUpdated(e) => json!({
+                "members": e.members.iter().map(Pubkey::to_string).collect::<Vec<_>>(),
+                "threshold": e.threshold,
+            }),
+            TrackerEvent::AdminActionProposed(e) => json!({
+                "proposal_id": e.proposal_id,
+                "proposer": e.proposer.to_string(),
+                "action": format!("{:?}", e.action),
+            }),
+            TrackerEvent::AdminActionApproved(e) => json!({
+                "proposal_id": e.proposal_id,
+                "member": e.member.to_string(),
+                "approvals": e.approvals,
+                "threshold": e.threshold,
+            }),
+            TrackerEvent::AdminActionExecuted(e) => json!({ "proposal_id": e.proposal_id }),
+            TrackerEvent::AdminActionCancelled(e) => json!({ "proposal_id": e.proposal_id }),
+            TrackerEvent::FeedStatusChanged(e) => json!({ "status": format!("{:?}", e.status) }),
+            TrackerEvent::OracleHaltChanged(e) => {
+                json!({ "oracle": e.oracle.to_string(), "halted": e.halted })
+            }
+            TrackerEvent::StakeDeposited(e) => json!({
+                "staker": e.staker.to_string(),
+                "amount": e.amount,
+                "bonded": e.bonded,
+            }),
+            TrackerEvent::UnstakeRequested(e) => json!({
+                "staker": e.staker.to_string(),
+                "amount": e.amount,
+                "unbonding_until": e.unbonding_until,
+            }),
+            TrackerEvent::StakeWithdrawn(e) => {
+                json!({ "staker": e.staker.to_string(), "amount": e.amount })
+            }
+            TrackerEvent::StakeSynced(e) => {
+                json!({ "staker": e.staker.to_string(), "bonded": e.bonded })
+            }
+            TrackerEvent::SlashApproved(e) => {
+                json!({ "oracle": e.oracle.to_string(), "amount": e.amount })
+            }
+            TrackerEvent::OracleSlashed(e) => {
+                json!({ "oracle": e.oracle.to_string(), "amount": e.amount })
+            }
+            TrackerEvent::RewardsFunded(e) => {
+                json!({ "funder": e.funder.to_string(), "amount": e.amount })
+            }
+            TrackerEvent::RewardsClaimed(e) => {
+                json!({ "oracle": e.oracle.to_string(), "amount": e.amount })
+            }
+            TrackerEvent::FeedDelegated(e) => json!({
+                "validator": e.validator.map(|validator| validator.to_string()),
+                "commit_frequency_ms": e.commit_frequency_ms,
+            }),
+            TrackerEvent::FeedUndelegated(_) => json!({}),
+            TrackerEvent::CommitScheduled(e) => {
+                json!({ "aggregate_rate": e.aggregate_rate, "timestamp": e.timestamp })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Event;
+
+    #[test]
+    fn decodes_by_discriminator() {
+        let rate_data = Pubkey::new_unique();
+        let data = OracleRemoved { rate_data, oracle: Pubkey::new_unique(), forfeited_rewards: 7 }
+            .data();
+        let event = TrackerEvent::decode(&data).unwrap();
+        assert_eq!((event.name(), event.rate_data()), ("OracleRemoved", rate_data));
+
+        // The same fields under another event's discriminator decode as that event.
+        let mut data = data;
+        data[..8].copy_from_slice(RewardsFunded::DISCRIMINATOR);
+        let event = TrackerEvent::decode(&data).unwrap();
+        assert_eq!(event.name(), "RewardsFunded");
+        assert_eq!(event.details()["amount"], 7);
+    }
+
+    #[test]
+    fn skips_unknown_or_truncated_events() {
+        assert!(TrackerEvent::decode(&[0; 8]).is_none());
+        assert!(TrackerEvent::decode(&[1, 2, 3]).is_none());
+        let data = OracleRemoved {
+            rate_data: Pubkey::new_unique(),
+            oracle: Pubkey::new_unique(),
+            forfeited_rewards: 0,
+        }
+        .data();
+        assert!(TrackerEvent::decode(&data[..40]).is_none());
+    }
+}
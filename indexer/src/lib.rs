@@ -0,0 +1,18 @@
+// Indexes the exchange rate tracker's events into SQLite.
+//
+// Transactions come from a recorded fixture or a validator's RPC (`source`), the
+// tracker's `Program data:` lines are picked out of their logs (`logs`) and
+// decoded with the program's own event types (`events`), and the results are
+// written to a schema built for time-range queries (`store`).
+
+pub mod events;
+pub mod logs;
+pub mod source;
+pub mod store;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+// The tracker's program id as it appears in logs.
+pub fn program_id() -> String {
+    fiat_crypto_tracker::ID.to_string()
+}
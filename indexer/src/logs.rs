@@ -0,0 +1,86 @@
+// Finding the tracker's events in a transaction's log messages.
+//
+// `emit!` writes each event as a `Program data: <base64>` line. Other programs in
+// the same transaction can write such lines too, so the invocation stack is
+// followed through the `invoke` / `success` / `failed` lines and only data
+// written while the tracker is the innermost program is kept.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+const DATA_PREFIX: &str = "Program data: ";
+
+// The raw event data the program `program_id` emitted, in log order. Lines that
+// are not valid base64 are skipped.
+pub fn program_data(logs: &[String], program_id: &str) -> Vec<Vec<u8>> {
+    let mut stack: Vec<&str> = Vec::new();
+    let mut data = Vec::new();
+    for line in logs {
+        if let Some(data_line) = line.strip_prefix(DATA_PREFIX) {
+            if stack.last() == Some(&program_id) {
+                if let Ok(bytes) = STANDARD.decode(data_line.trim()) {
+                    data.push(bytes);
+                }
+            }
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        if words.next() != Some("Program") {
+            continue;
+        }
+        let (Some(program), Some(status)) = (words.next(), words.next()) else {
+            continue;
+        };
+        match status {
+            "invoke" => stack.push(program),
+            "success" | "failed:" => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACKER: &str = "2Q4J9MoBr6eM8jBBzPcDbSTfG7rKLsm68mYDDLfDZ5kE";
+    const CONSUMER: &str = "FBuSgTGWYqJeZHAjKKXeEdVB8rX4fmrGZrJDev4C3tHF";
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn keeps_only_the_trackers_data() {
+        let logs = logs(&[
+            &format!("Program {} invoke [1]", CONSUMER),
+            "Program data: AQI=",
+            &format!("Program {} invoke [2]", TRACKER),
+            "Program log: Instruction: UpdateRate",
+            "Program data: AwQ=",
+            &format!("Program {} consumed 5000 of 190000 compute units", TRACKER),
+            &format!("Program {} success", TRACKER),
+            "Program data: BQY=",
+            &format!("Program {} success", CONSUMER),
+        ]);
+        assert_eq!(program_data(&logs, TRACKER), [vec![3, 4]]);
+        assert_eq!(program_data(&logs, CONSUMER), [vec![1, 2], vec![5, 6]]);
+    }
+
+    #[test]
+    fn pops_failed_invocations() {
+        let logs = logs(&[
+            &format!("Program {} invoke [1]", TRACKER),
+            &format!("Program {} invoke [2]", CONSUMER),
+            &format!("Program {} failed: custom program error: 0x1", CONSUMER),
+            "Program data: Bwg=",
+            "Program data: not base64!",
+            &format!("Program {} success", TRACKER),
+        ]);
+        assert_eq!(program_data(&logs, TRACKER), [vec![7, 8]]);
+    }
+}
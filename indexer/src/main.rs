@@ -0,0 +1,79 @@
+// Command line entry point for the indexer.
+//
+//   fiat-crypto-tracker-indexer --db rates.sqlite --fixture transactions.json
+//   fiat-crypto-tracker-indexer --db rates.sqlite --rpc http://127.0.0.1:8899 [--poll 5]
+//
+// `--program-id` overrides the tracker's program id, e.g. for a redeployment.
+
+use std::time::Duration;
+
+use fiat_crypto_tracker_indexer::source::{load_fixture, Rpc, Transaction};
+use fiat_crypto_tracker_indexer::store::Store;
+use fiat_crypto_tracker_indexer::{program_id, Result};
+
+const USAGE: &str = "usage: fiat-crypto-tracker-indexer --db <path> \
+    (--fixture <path> | --rpc <url> [--poll <secs>]) [--program-id <id>]";
+
+struct Args {
+    db: String,
+    fixture: Option<String>,
+    rpc: Option<String>,
+    poll_secs: Option<u64>,
+    program_id: String,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        db: String::new(),
+        fixture: None,
+        rpc: None,
+        poll_secs: None,
+        program_id: program_id(),
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(flag) = argv.next() {
+        let value = argv.next().ok_or(USAGE)?;
+        match flag.as_str() {
+            "--db" => args.db = value,
+            "--fixture" => args.fixture = Some(value),
+            "--rpc" => args.rpc = Some(value),
+            "--poll" => args.poll_secs = Some(value.parse()?),
+            "--program-id" => args.program_id = value,
+            _ => return Err(USAGE.into()),
+        }
+    }
+    if args.db.is_empty() || args.fixture.is_some() == args.rpc.is_some() {
+        return Err(USAGE.into());
+    }
+    Ok(args)
+}
+
+fn index_all(store: &mut Store, transactions: &[Transaction], program_id: &str) -> Result<()> {
+    let mut events = 0;
+    for tx in transactions {
+        events += store.index(tx, program_id)?.unwrap_or(0);
+    }
+    println!("Indexed {} event(s) from {} transaction(s).", events, transactions.len());
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    let mut store = Store::open(&args.db)?;
+
+    if let Some(path) = &args.fixture {
+        let transactions = load_fixture(&std::fs::read_to_string(path)?)?;
+        return index_all(&mut store, &transactions, &args.program_id);
+    }
+
+    let rpc = Rpc::new(args.rpc.as_deref().unwrap_or_default());
+    loop {
+        let until = store.latest_signature()?;
+        let transactions = rpc.transactions_since(&args.program_id, until.as_deref())?;
+        index_all(&mut store, &transactions, &args.program_id)?;
+        match args.poll_secs {
+            Some(secs) => std::thread::sleep(Duration::from_secs(secs)),
+            None => return Ok(()),
+        }
+    }
+}
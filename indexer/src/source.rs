@@ -0,0 +1,165 @@
+// Where transactions come from: a recorded JSON fixture or a validator's RPC.
+//
+// Both use the shape `getTransaction` returns with `"encoding": "json"`, so a
+// fixture is simply an array of recorded `getTransaction` results.
+
+use serde_json::{json, Value};
+
+use crate::Result;
+
+// The parts of a confirmed transaction the indexer needs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub succeeded: bool,
+    pub logs: Vec<String>,
+}
+
+impl Transaction {
+    // Reads a `getTransaction` result.
+    pub fn from_rpc(value: &Value) -> Result<Self> {
+        let signature = value["transaction"]["signatures"][0]
+            .as_str()
+            .ok_or("transaction has no signature")?;
+        let slot = value["slot"].as_u64().ok_or("transaction has no slot")?;
+        let meta = &value["meta"];
+        let logs = meta["logMessages"]
+            .as_array()
+            .map(|lines| lines.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            .unwrap_or_default();
+        Ok(Transaction {
+            signature: signature.to_string(),
+            slot,
+            block_time: value["blockTime"].as_i64(),
+            succeeded: meta["err"].is_null(),
+            logs,
+        })
+    }
+}
+
+// Reads a fixture: a JSON array of `getTransaction` results.
+pub fn load_fixture(json: &str) -> Result<Vec<Transaction>> {
+    let value: Value = serde_json::from_str(json)?;
+    value
+        .as_array()
+        .ok_or("fixture must be a JSON array of transactions")?
+        .iter()
+        .map(Transaction::from_rpc)
+        .collect()
+}
+
+// Signatures returned per `getSignaturesForAddress` page, the RPC's maximum.
+const SIGNATURE_PAGE: usize = 1000;
+
+// A minimal JSON-RPC client for the two calls the indexer makes.
+pub struct Rpc {
+    url: String,
+}
+
+impl Rpc {
+    pub fn new(url: &str) -> Self {
+        Rpc { url: url.to_string() }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let mut response: Value = ureq::post(&self.url).send_json(request)?.into_json()?;
+        if !response["error"].is_null() {
+            return Err(format!("{} failed: {}", method, response["error"]).into());
+        }
+        Ok(response["result"].take())
+    }
+
+    // Every confirmed transaction mentioning `program_id` after the one signed
+    // `until` (or all of them if `None`), oldest first.
+    pub fn transactions_since(
+        &self,
+        program_id: &str,
+        until: Option<&str>,
+    ) -> Result<Vec<Transaction>> {
+        let mut signatures = Vec::new();
+        let mut before: Option<String> = None;
+        loop {
+            let page = self.call(
+                "getSignaturesForAddress",
+                json!([program_id, {
+                    "until": until,
+                    "before": before,
+                    "limit": SIGNATURE_PAGE,
+                    "commitment": "confirmed",
+                }]),
+            )?;
+            let page = page.as_array().ok_or("expected a list of signatures")?;
+            for entry in page {
+                let signature = entry["signature"].as_str().ok_or("missing signature")?;
+                signatures.push(signature.to_string());
+            }
+            if page.len() < SIGNATURE_PAGE {
+                break;
+            }
+            before = signatures.last().cloned();
+        }
+
+        // Pages come newest first.
+        signatures
+            .iter()
+            .rev()
+            .map(|signature| {
+                let value = self.call(
+                    "getTransaction",
+                    json!([signature, {
+                        "encoding": "json",
+                        "commitment": "confirmed",
+                        "maxSupportedTransactionVersion": 0,
+                    }]),
+                )?;
+                Transaction::from_rpc(&value)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_get_transaction_results() {
+        let transactions = load_fixture(
+            r#"[
+                {
+                    "slot": 42,
+                    "blockTime": 1700000000,
+                    "meta": { "err": null, "logMessages": ["Program log: hi"] },
+                    "transaction": { "signatures": ["sig1"] }
+                },
+                {
+                    "slot": 43,
+                    "blockTime": null,
+                    "meta": { "err": { "InstructionError": [0, { "Custom": 6000 }] } },
+                    "transaction": { "signatures": ["sig2"] }
+                }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            transactions[0],
+            Transaction {
+                signature: "sig1".to_string(),
+                slot: 42,
+                block_time: Some(1_700_000_000),
+                succeeded: true,
+                logs: vec!["Program log: hi".to_string()],
+            }
+        );
+        assert!(!transactions[1].succeeded);
+        assert_eq!(transactions[1].block_time, None);
+        assert!(transactions[1].logs.is_empty());
+
+        assert!(load_fixture(r#"{ "slot": 1 }"#).is_err());
+        assert!(load_fixture(r#"[{ "slot": 1 }]"#).is_err());
+    }
+}
@@ -0,0 +1,413 @@
+// The SQLite database the indexer writes to.
+//
+// `rate_updates` holds every submitted rate, accepted or dropped as an outlier,
+// indexed by feed and time for range queries. `feeds` and `oracles` track the
+// current roster, and `admin_actions` keeps every other event with its fields
+// as JSON. Each transaction is indexed at most once, so replaying overlapping
+// fixtures or RPC pages is safe.
+//
+// Rates are u64 but SQLite integers are i64; a rate above `i64::MAX` fails the
+// transaction rather than being stored wrapped.
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::events::TrackerEvent;
+use crate::logs::program_data;
+use crate::source::Transaction;
+use crate::Result;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    block_time INTEGER
+);
+CREATE INDEX IF NOT EXISTS transactions_by_slot ON transactions (slot);
+
+CREATE TABLE IF NOT EXISTS feeds (
+    rate_data TEXT PRIMARY KEY,
+    base TEXT NOT NULL,
+    quote TEXT NOT NULL,
+    exponent INTEGER NOT NULL,
+    authority TEXT NOT NULL,
+    created_slot INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS oracles (
+    rate_data TEXT NOT NULL,
+    oracle TEXT NOT NULL,
+    name TEXT NOT NULL,
+    added_slot INTEGER NOT NULL,
+    removed_slot INTEGER,
+    PRIMARY KEY (rate_data, oracle)
+);
+
+CREATE TABLE IF NOT EXISTS rate_updates (
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    rate_data TEXT NOT NULL,
+    oracle TEXT NOT NULL,
+    rate INTEGER NOT NULL,
+    accepted INTEGER NOT NULL,
+    old_rate INTEGER,
+    change_bps INTEGER,
+    aggregate_rate INTEGER NOT NULL,
+    num_contributors INTEGER,
+    PRIMARY KEY (signature, event_index)
+);
+CREATE INDEX IF NOT EXISTS rate_updates_by_feed_time ON rate_updates (rate_data, timestamp);
+CREATE INDEX IF NOT EXISTS rate_updates_by_oracle_time ON rate_updates (oracle, timestamp);
+
+CREATE TABLE IF NOT EXISTS admin_actions (
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    rate_data TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    details TEXT NOT NULL,
+    PRIMARY KEY (signature, event_index)
+);
+CREATE INDEX IF NOT EXISTS admin_actions_by_feed_time ON admin_actions (rate_data, block_time);
+";
+
+// One row of `rate_updates`, as returned by `Store::rate_updates`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateUpdateRow {
+    pub timestamp: i64,
+    pub oracle: String,
+    pub rate: u64,
+    pub accepted: bool,
+    pub aggregate_rate: u64,
+}
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Store { conn })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    // The most recently indexed transaction's signature, to resume from.
+    pub fn latest_signature(&self) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT signature FROM transactions ORDER BY slot DESC, rowid DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // Indexes the events `program_id` emitted in `tx`. Returns how many were
+    // stored, or `None` if the transaction was already indexed. Failed
+    // transactions are recorded but their events are ignored, since nothing they
+    // did took effect.
+    pub fn index(&mut self, tx: &Transaction, program_id: &str) -> Result<Option<usize>> {
+        let db = self.conn.transaction()?;
+        let inserted = db.execute(
+            "INSERT OR IGNORE INTO transactions (signature, slot, block_time) VALUES (?1, ?2, ?3)",
+            params![tx.signature, tx.slot, tx.block_time],
+        )?;
+        if inserted == 0 {
+            return Ok(None);
+        }
+
+        let events: Vec<TrackerEvent> = if tx.succeeded {
+            program_data(&tx.logs, program_id)
+                .iter()
+                .filter_map(|data| TrackerEvent::decode(data))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        for (index, event) in events.iter().enumerate() {
+            let rate_data = event.rate_data().to_string();
+            match event {
+                TrackerEvent::RateUpdated(e) => {
+                    db.execute(
+                        "INSERT INTO rate_updates (signature, event_index, slot, timestamp,
+                            rate_data, oracle, rate, accepted, old_rate, change_bps,
+                            aggregate_rate, num_contributors)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 1, ?8, ?9, ?10, ?11)",
+                        params![
+                            tx.signature,
+                            index,
+                            tx.slot,
+                            e.timestamp,
+                            rate_data,
+                            e.oracle.to_string(),
+                            e.new_rate,
+                            e.old_rate,
+                            e.change_bps,
+                            e.aggregate_rate,
+                            e.num_contributors,
+                        ],
+                    )?;
+                    continue;
+                }
+                TrackerEvent::RateRejected(e) => {
+                    db.execute(
+                        "INSERT INTO rate_updates (signature, event_index, slot, timestamp,
+                            rate_data, oracle, rate, accepted, aggregate_rate)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, ?8)",
+                        params![
+                            tx.signature,
+                            index,
+                            tx.slot,
+                            e.timestamp,
+                            rate_data,
+                            e.oracle.to_string(),
+                            e.rate,
+                            e.aggregate_rate,
+                        ],
+                    )?;
+                    continue;
+                }
+                TrackerEvent::TrackerInitialized(e) => {
+                    db.execute(
+                        "INSERT OR REPLACE INTO feeds
+                            (rate_data, base, quote, exponent, authority, created_slot)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![
+                            rate_data,
+                            e.base,
+                            e.quote,
+                            e.exponent,
+                            e.authority.to_string(),
+                            tx.slot
+                        ],
+                    )?;
+                }
+                TrackerEvent::AuthorityTransferAccepted(e) => {
+                    db.execute(
+                        "UPDATE feeds SET authority = ?2 WHERE rate_data = ?1",
+                        params![rate_data, e.new_authority.to_string()],
+                    )?;
+                }
+                TrackerEvent::OracleAdded(e) => {
+                    // An oracle can be removed and added again.
+                    db.execute(
+                        "INSERT INTO oracles (rate_data, oracle, name, added_slot)
+                         VALUES (?1, ?2, ?3, ?4)
+                         ON CONFLICT (rate_data, oracle) DO UPDATE
+                         SET name = excluded.name, added_slot = excluded.added_slot,
+                             removed_slot = NULL",
+                        params![rate_data, e.oracle.to_string(), e.name, tx.slot],
+                    )?;
+                }
+                TrackerEvent::OracleRemoved(e) => {
+                    db.execute(
+                        "UPDATE oracles SET removed_slot = ?3 WHERE rate_data = ?1 AND oracle = ?2",
+                        params![rate_data, e.oracle.to_string(), tx.slot],
+                    )?;
+                }
+                TrackerEvent::OracleKeyReplaced(e) => {
+                    // The new key can only have a row left from an earlier removal,
+                    // which the renamed oracle replaces.
+                    db.execute(
+                        "DELETE FROM oracles WHERE rate_data = ?1 AND oracle = ?2",
+                        params![rate_data, e.new_pubkey.to_string()],
+                    )?;
+                    db.execute(
+                        "UPDATE oracles SET oracle = ?3 WHERE rate_data = ?1 AND oracle = ?2",
+                        params![rate_data, e.old_pubkey.to_string(), e.new_pubkey.to_string()],
+                    )?;
+                }
+                _ => {}
+            }
+            db.execute(
+                "INSERT INTO admin_actions
+                    (signature, event_index, slot, block_time, rate_data, kind, details)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    tx.signature,
+                    index,
+                    tx.slot,
+                    tx.block_time,
+                    rate_data,
+                    event.name(),
+                    event.details().to_string(),
+                ],
+            )?;
+        }
+        db.commit()?;
+        Ok(Some(events.len()))
+    }
+
+    // The rates submitted to the feed at `rate_data` with timestamps in
+    // `[from, to)`, oldest first.
+    pub fn rate_updates(&self, rate_data: &str, from: i64, to: i64) -> Result<Vec<RateUpdateRow>> {
+        let mut statement = self.conn.prepare(
+            "SELECT timestamp, oracle, rate, accepted, aggregate_rate FROM rate_updates
+             WHERE rate_data = ?1 AND timestamp >= ?2 AND timestamp < ?3
+             ORDER BY timestamp, slot, event_index",
+        )?;
+        let rows = statement.query_map(params![rate_data, from, to], |row| {
+            Ok(RateUpdateRow {
+                timestamp: row.get(0)?,
+                oracle: row.get(1)?,
+                rate: row.get(2)?,
+                accepted: row.get(3)?,
+                aggregate_rate: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::load_fixture;
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::Event;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use fiat_crypto_tracker::{OracleAdded, OracleKeyReplaced};
+
+    const FEED: &str = "6anbDQNCcVh2f6okexjaX1VGj6tEnizJ1kV5UTBS8Zhi";
+    const BANK: &str = "AUH6c4QLMr2qQr9N5Kkpz5astDM9gBNroXCSxQiFTGQv";
+    const P2P: &str = "DYougPS3ao5Ticdy5bFcKKcXgSjHVJ2yuwaMgxHpPoQr";
+    const P2P_ROTATED: &str = "B1rADWGjAKbZYVHMYhS5ZKyMbqFB65vmNNxUXdgFBoh3";
+
+    // A USD/NGN feed's history: two oracles report, an update fails, an outlier
+    // is dropped, another program reads the feed over CPI, then one oracle's key
+    // is rotated and the other is removed.
+    fn indexed() -> (Store, Vec<Transaction>) {
+        let transactions = load_fixture(include_str!("../fixtures/transactions.json")).unwrap();
+        let mut store = Store::open_in_memory().unwrap();
+        for tx in &transactions {
+            store.index(tx, &crate::program_id()).unwrap().unwrap();
+        }
+        (store, transactions)
+    }
+
+    // A successful transaction in which the tracker emitted `events`.
+    fn emitting(signature: &str, slot: u64, events: &[Vec<u8>]) -> Transaction {
+        let program_id = crate::program_id();
+        let mut logs = vec![format!("Program {} invoke [1]", program_id)];
+        logs.extend(events.iter().map(|data| format!("Program data: {}", STANDARD.encode(data))));
+        logs.push(format!("Program {} success", program_id));
+        Transaction {
+            signature: signature.to_string(),
+            slot,
+            block_time: None,
+            succeeded: true,
+            logs,
+        }
+    }
+
+    fn count(store: &Store, sql: &str) -> i64 {
+        store.connection().query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn indexes_a_recorded_history() {
+        let (store, transactions) = indexed();
+        let feed: (String, String, u8) = store
+            .connection()
+            .query_row(
+                "SELECT base, quote, exponent FROM feeds WHERE rate_data = ?1",
+                [FEED],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(feed, ("USD".to_string(), "NGN".to_string(), 2));
+
+        // The failed update and the consumer's own data are not indexed.
+        assert_eq!(count(&store, "SELECT COUNT(*) FROM rate_updates"), 4);
+        assert_eq!(count(&store, "SELECT COUNT(*) FROM rate_updates WHERE accepted = 0"), 1);
+        assert_eq!(count(&store, "SELECT COUNT(*) FROM admin_actions"), 6);
+        let details: String = store
+            .connection()
+            .query_row(
+                "SELECT details FROM admin_actions WHERE kind = 'SettingsChanged'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(details.contains("SetMaxDeviation { max_deviation_bps: 250 }"), "{}", details);
+
+        let roster: Vec<(String, Option<u64>)> = store
+            .connection()
+            .prepare("SELECT oracle, removed_slot FROM oracles ORDER BY added_slot")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        let removed_at = transactions.last().unwrap().slot;
+        assert_eq!(roster, [(BANK.to_string(), Some(removed_at)), (P2P_ROTATED.to_string(), None)]);
+
+        let latest = store.latest_signature().unwrap();
+        assert_eq!(latest.as_deref(), Some(transactions.last().unwrap().signature.as_str()));
+    }
+
+    #[test]
+    fn rotates_in_a_key_that_was_removed_earlier() {
+        let (mut store, transactions) = indexed();
+        let slot = transactions.last().unwrap().slot;
+        let feed: Pubkey = FEED.parse().unwrap();
+        let (new_bank, bank) = (Pubkey::new_unique(), BANK.parse().unwrap());
+
+        // The fixture ends by removing BANK, whose key then comes back as a new
+        // oracle's rotated key.
+        let added = OracleAdded { rate_data: feed, oracle: new_bank, name: "Bank".to_string() };
+        store.index(&emitting("add", slot + 1, &[added.data()]), &crate::program_id()).unwrap();
+        let replaced =
+            OracleKeyReplaced { rate_data: feed, old_pubkey: new_bank, new_pubkey: bank };
+        let tx = emitting("replace", slot + 2, &[replaced.data()]);
+        assert_eq!(store.index(&tx, &crate::program_id()).unwrap(), Some(1));
+
+        let bank_row: (String, u64, Option<u64>) = store
+            .connection()
+            .query_row(
+                "SELECT name, added_slot, removed_slot FROM oracles WHERE oracle = ?1",
+                [BANK],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(bank_row, ("Bank".to_string(), slot + 1, None));
+        assert_eq!(count(&store, "SELECT COUNT(*) FROM oracles"), 2);
+    }
+
+    #[test]
+    fn queries_updates_by_time() {
+        let (store, _) = indexed();
+        let updates = store.rate_updates(FEED, 1_700_000_008, 1_700_000_017).unwrap();
+        let summary: Vec<(&str, u64, bool)> =
+            updates.iter().map(|u| (u.oracle.as_str(), u.rate, u.accepted)).collect();
+        assert_eq!(summary, [(BANK, 145_000, true), (P2P, 146_000, true), (P2P, 160_000, false)]);
+        assert_eq!(updates[1].aggregate_rate, 145_500);
+        assert!(store.rate_updates(P2P, 0, i64::MAX).unwrap().is_empty());
+    }
+
+    #[test]
+    fn indexes_each_transaction_once() {
+        let (mut store, transactions) = indexed();
+        for tx in &transactions {
+            assert_eq!(store.index(tx, &crate::program_id()).unwrap(), None);
+        }
+        assert_eq!(count(&store, "SELECT COUNT(*) FROM rate_updates"), 4);
+        assert_eq!(count(&store, "SELECT COUNT(*) FROM transactions"), 12);
+    }
+}
@@ -7,6 +7,18 @@ import "dotenv/config";
 
 // --- CONFIGURATION ---
 
+// The currency pair this service publishes, e.g. PAIR_BASE=GHS and PAIR_QUOTE=NGN
+// in your .env file. The pair's rate_data PDA is derived from it.
+const PAIR = {
+    base: process.env.PAIR_BASE || "USD",
+    quote: process.env.PAIR_QUOTE || "NGN",
+};
+
+// Binance lists USDT rather than USD pairs; set BINANCE_SYMBOL if the pair is listed
+// under another symbol.
+const BINANCE_SYMBOL =
+    process.env.BINANCE_SYMBOL || `${PAIR.base === "USD" ? "USDT" : PAIR.base}${PAIR.quote}`;
+
 
 function setupProviderAndProgram() {
     // Make sure your Anchor.toml points to the correct cluster (e.g., localhost or devnet)
@@ -40,16 +52,17 @@ async function fetchBankRate() {
             return 0; // Return a default value or handle the error
         }
 
-        // Use the API endpoint with NGN as the base currency
-        const response = await fetch(`https://v6.exchangerate-api.com/v6/${apiKey}/latest/NGN`);
+        // The API returns the value of 1 unit of the base currency in every other one.
+        const response = await fetch(`https://v6.exchangerate-api.com/v6/${apiKey}/latest/${PAIR.base}`);
         const data = await response.json();
+        const rate = data.conversion_rates?.[PAIR.quote];
+        if (rate === undefined) {
+            console.error(`ExchangeRate-API has no ${PAIR.base}/${PAIR.quote} rate.`);
+            return 0;
+        }
 
-        // The API returns the value of 1 NGN in USD. We need the inverse.
-        const usdValue = data.conversion_rates.USD;
-        const ngnRate = 1 / usdValue;
-        
-        console.log(`  -> Got ExchangeRate-API Rate: ${ngnRate}`);
-        return Math.floor(ngnRate);
+        console.log(`  -> Got ExchangeRate-API Rate: ${rate}`);
+        return rate;
     } catch (error) {
         console.error("Error fetching from ExchangeRate-API:", error);
         return 0; // Return a default value on error
@@ -60,12 +73,13 @@ async function fetchBankRate() {
 async function fetchCryptoRate() {
     try {
         console.log("Fetching rate from Binance API...");
-        const response = await fetch('https://api.binance.com/api/v3/ticker/price?symbol=USDTNGN');
+        const response = await fetch(`https://api.binance.com/api/v3/ticker/price?symbol=${BINANCE_SYMBOL}`);
         const data = await response.json();
-        const ngnRate = parseFloat(data.price);
+        // Binance quotes prices as decimal strings; keep them that way for toScaledRate.
+        const rate = data.price ?? 0;
         
-        console.log(`  -> Got Binance Rate: ${ngnRate}`);
-        return Math.floor(ngnRate);
+        console.log(`  -> Got Binance Rate: ${rate}`);
+        return rate;
     } catch (error) {
         console.error("Error fetching from Binance API:", error);
         return 0; // Return a default value on error
@@ -74,30 +88,62 @@ async function fetchCryptoRate() {
 
 // --- ON-CHAIN UPDATE LOGIC ---
 
+// Derives the pair's feed account, seeded by its base and quote currency codes.
+function findRateDataPDA(program, pair) {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from("rate_data"), Buffer.from(pair.base), Buffer.from("/"), Buffer.from(pair.quote)],
+        program.programId
+    )[0];
+}
+
+// Derives the price history PDA that every update is appended to.
+function findRateHistoryPDA(program, rateDataAccountPubkey) {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from("rate_history"), rateDataAccountPubkey.toBuffer()],
+        program.programId
+    )[0];
+}
+
+// Converts a decimal rate into the feed's fixed-point representation without
+// going through floating point, e.g. "1450.25" with exponent 2 becomes 145025.
+// The fraction is padded or cut to `exponent` digits, rounding half up.
+function toScaledRate(rate, exponent) {
+    const match = /^(\d+)(?:\.(\d*))?$/.exec(String(rate).trim());
+    if (!match) {
+        throw new Error(`Cannot scale rate ${rate}: expected a plain decimal number.`);
+    }
+    const [, integer, fraction = ""] = match;
+    const scaled = new BN(integer + fraction.padEnd(exponent, "0").slice(0, exponent));
+    return (fraction[exponent] ?? "0") >= "5" ? scaled.addn(1) : scaled;
+}
+
 /**
  * Sends a transaction to the Solana program to update the rate for a specific oracle.
  * @param program - The initialized Anchor program instance.
  * @param rateDataAccountPubkey - The public key of the main data account.
  * @param oracleKeypair - The keypair of the oracle that is signing the transaction.
- * @param newRate - The new exchange rate to set.
+ * @param newRate - The new exchange rate to set, as a decimal string or number.
+ * @param exponent - The feed's exponent that on-chain rates are scaled by.
  */
 async function updateOnChainRate(
     program, // Removed TypeScript type annotations
     rateDataAccountPubkey,
     oracleKeypair,
-    newRate
+    newRate,
+    exponent
 ) {
     // Do not send an update if the rate is 0 (which indicates an API error)
-    if (newRate === 0) {
+    if (!(Number(newRate) > 0)) {
         console.log(`Skipping update for oracle ${oracleKeypair.publicKey.toBase58()} due to invalid rate.`);
         return;
     }
 
     try {
         const tx = await program.methods
-           .updateRate(new BN(newRate))
+           .updateRate(PAIR, toScaledRate(newRate, exponent))
             .accounts({
                 rateData: rateDataAccountPubkey,
+                rateHistory: findRateHistoryPDA(program, rateDataAccountPubkey),
                 oracle: oracleKeypair.publicKey,
             })
             .signers([oracleKeypair]) // The oracle signs to authorize the update
@@ -116,19 +162,22 @@ async function main() {
     console.log("Starting Oracle Service...");
 
     // 1. Load configuration from environment variables
-    if (!process.env.RATE_DATA_ACCOUNT_PUBKEY || !process.env.EXCHANGERATE_ORACLE_SECRET_KEY || !process.env.BINANCE_ORACLE_SECRET_KEY) {
-        console.error("Please set RATE_DATA_ACCOUNT_PUBKEY, EXCHANGERATE_ORACLE_SECRET_KEY, and BINANCE_ORACLE_SECRET_KEY in your .env file.");
+    if (!process.env.EXCHANGERATE_ORACLE_SECRET_KEY || !process.env.BINANCE_ORACLE_SECRET_KEY) {
+        console.error("Please set EXCHANGERATE_ORACLE_SECRET_KEY and BINANCE_ORACLE_SECRET_KEY in your .env file.");
         return;
     }
     
-    const rateDataAccountPubkey = new PublicKey(process.env.RATE_DATA_ACCOUNT_PUBKEY);
     const exchangeRateApiKeypair = loadKeypairFromSecret(process.env.EXCHANGERATE_ORACLE_SECRET_KEY);
     const binanceApiKeypair = loadKeypairFromSecret(process.env.BINANCE_ORACLE_SECRET_KEY);
     
     // 2. Initialize connection to the program
     const program = setupProviderAndProgram();
+    const rateDataAccountPubkey = findRateDataPDA(program, PAIR);
+
+    // Rates are submitted scaled by the exponent the feed was initialized with.
+    const { exponent } = await program.account.rateData.fetch(rateDataAccountPubkey);
 
-    console.log(`Oracle service configured for data account: ${rateDataAccountPubkey.toBase58()}`);
+    console.log(`Oracle service configured for ${PAIR.base}/${PAIR.quote}, data account: ${rateDataAccountPubkey.toBase58()} (exponent ${exponent})`);
     console.log(`ExchangeRate-API Oracle Pubkey: ${exchangeRateApiKeypair.publicKey.toBase58()}`);
     console.log(`Binance Oracle Pubkey: ${binanceApiKeypair.publicKey.toBase58()}`);
 
@@ -144,8 +193,8 @@ async function main() {
         ]);
 
         // Update the on-chain data for each oracle
-        await updateOnChainRate(program, rateDataAccountPubkey, exchangeRateApiKeypair, bankRate);
-        await updateOnChainRate(program, rateDataAccountPubkey, binanceApiKeypair, cryptoRate);
+        await updateOnChainRate(program, rateDataAccountPubkey, exchangeRateApiKeypair, bankRate, exponent);
+        await updateOnChainRate(program, rateDataAccountPubkey, binanceApiKeypair, cryptoRate, exponent);
 
         console.log("--- Update cycle finished ---\n");
 
@@ -0,0 +1,227 @@
+// A stand-in for MagicBlock's delegation program, used by the tests to exercise
+// `delegate_rate_data` and `process_undelegation` on a local validator. It is
+// loaded at the real program's address and takes the same `Delegate`
+// instruction, recording who each account was delegated from. `undelegate`
+// hands an account back the way the real program does once a rollup has
+// committed it for undelegation; here anyone can call it.
+
+// `#[program]` expands to IDL account handlers that still call the deprecated
+// `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::system_program;
+
+declare_id!("DELeGGvXpWV2fqJUhqcF5ZSYMS4JTLjteaAMARRSaeSh");
+
+pub const DELEGATE_BUFFER_SEED: &[u8] = b"buffer";
+pub const DELEGATION_RECORD_SEED: &[u8] = b"delegation";
+pub const DELEGATION_METADATA_SEED: &[u8] = b"delegation-metadata";
+pub const UNDELEGATE_BUFFER_SEED: &[u8] = b"undelegate-buffer";
+// The owner program's `process_undelegation` instruction.
+pub const EXTERNAL_UNDELEGATE_DISCRIMINATOR: [u8; 8] = [196, 28, 41, 206, 48, 37, 51, 167];
+
+#[program]
+pub mod delegation_stub {
+    use super::*;
+
+    // Takes over an account its owner has emptied and assigned to this program,
+    // restoring its data from the owner's buffer.
+    #[instruction(discriminator = [0, 0, 0, 0, 0, 0, 0, 0])]
+    pub fn delegate(ctx: Context<Delegate>, args: DelegateAccountArgs) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let seeds: Vec<&[u8]> = args.seeds.iter().map(Vec::as_slice).collect();
+        let (expected, _) = Pubkey::find_program_address(&seeds, accounts.owner_program.key);
+        require_keys_eq!(
+            accounts.delegated_account.key(),
+            expected,
+            anchor_lang::error::ErrorCode::ConstraintSeeds
+        );
+        accounts
+            .delegated_account
+            .try_borrow_mut_data()?
+            .copy_from_slice(&accounts.buffer.try_borrow_data()?);
+
+        let delegated = accounts.delegated_account.key();
+        let record = &mut ctx.accounts.delegation_record;
+        record.owner = ctx.accounts.owner_program.key();
+        record.validator = args.validator;
+        record.commit_frequency_ms = args.commit_frequency_ms;
+        record.delegation_slot = Clock::get()?.slot;
+        ctx.accounts.delegation_metadata.seeds = args.seeds;
+        msg!("Delegated {} from {}", delegated, record.owner);
+        Ok(())
+    }
+
+    // Closes a delegated account and has its owner re-create it from a buffer
+    // holding its data, then checks that the owner did.
+    #[instruction(discriminator = [3, 0, 0, 0, 0, 0, 0, 0])]
+    pub fn undelegate(ctx: Context<Undelegate>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let account = accounts.delegated_account.to_account_info();
+        let buffer = accounts.undelegate_buffer.to_account_info();
+        let payer = accounts.payer.to_account_info();
+        let buffer_bump = [ctx.bumps.undelegate_buffer];
+        let buffer_seeds: &[&[u8]] = &[UNDELEGATE_BUFFER_SEED, account.key.as_ref(), &buffer_bump];
+
+        let len = account.data_len();
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                accounts.system_program.to_account_info(),
+                system_program::CreateAccount { from: payer.clone(), to: buffer.clone() },
+                &[buffer_seeds],
+            ),
+            Rent::get()?.minimum_balance(len),
+            len as u64,
+            &crate::ID,
+        )?;
+        buffer.try_borrow_mut_data()?.copy_from_slice(&account.try_borrow_data()?);
+        close(&account, &payer)?;
+
+        let mut data = EXTERNAL_UNDELEGATE_DISCRIMINATOR.to_vec();
+        accounts.delegation_metadata.seeds.serialize(&mut data)?;
+        let instruction = Instruction {
+            program_id: accounts.owner_program.key(),
+            accounts: vec![
+                AccountMeta::new(account.key(), false),
+                AccountMeta::new_readonly(buffer.key(), true),
+                AccountMeta::new(payer.key(), true),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data,
+        };
+        invoke_signed(
+            &instruction,
+            &[
+                account.clone(),
+                buffer.clone(),
+                payer.clone(),
+                accounts.system_program.to_account_info(),
+                accounts.owner_program.to_account_info(),
+            ],
+            &[buffer_seeds],
+        )?;
+
+        require_keys_eq!(
+            *account.owner,
+            accounts.owner_program.key(),
+            anchor_lang::error::ErrorCode::ConstraintOwner
+        );
+        require!(
+            *account.try_borrow_data()? == *buffer.try_borrow_data()?,
+            anchor_lang::error::ErrorCode::ConstraintRaw
+        );
+        close(&buffer, &payer)?;
+        msg!("Undelegated {} to {}", account.key, accounts.owner_program.key);
+        Ok(())
+    }
+}
+
+// Closes `account`, owned by this program, refunding its lamports to `to`.
+fn close<'info>(account: &AccountInfo<'info>, to: &AccountInfo<'info>) -> Result<()> {
+    **to.try_borrow_mut_lamports()? += account.lamports();
+    **account.try_borrow_mut_lamports()? = 0;
+    account.resize(0)?;
+    account.assign(&system_program::ID);
+    Ok(())
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DelegateAccountArgs {
+    pub commit_frequency_ms: u32,
+    pub seeds: Vec<Vec<u8>>,
+    pub validator: Option<Pubkey>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: DelegateAccountArgs)]
+pub struct Delegate<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    // Emptied and assigned to this program by its owner, which signs for it.
+    #[account(mut, owner = crate::ID)]
+    pub delegated_account: Signer<'info>,
+    /// CHECK: The program the account is delegated from.
+    #[account(executable)]
+    pub owner_program: UncheckedAccount<'info>,
+    /// CHECK: The owner's copy of the account's data.
+    #[account(
+        owner = owner_program.key(),
+        seeds = [DELEGATE_BUFFER_SEED, delegated_account.key().as_ref()],
+        bump,
+        seeds::program = owner_program.key()
+    )]
+    pub buffer: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = payer,
+        space = DelegationRecord::SPACE,
+        seeds = [DELEGATION_RECORD_SEED, delegated_account.key().as_ref()],
+        bump
+    )]
+    pub delegation_record: Account<'info, DelegationRecord>,
+    #[account(
+        init,
+        payer = payer,
+        space = DelegationMetadata::space(&args.seeds),
+        seeds = [DELEGATION_METADATA_SEED, delegated_account.key().as_ref()],
+        bump
+    )]
+    pub delegation_metadata: Account<'info, DelegationMetadata>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Undelegate<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Delegated to this program, as the record shows.
+    #[account(mut, owner = crate::ID)]
+    pub delegated_account: UncheckedAccount<'info>,
+    /// CHECK: The program the account was delegated from.
+    #[account(executable, address = delegation_record.owner)]
+    pub owner_program: UncheckedAccount<'info>,
+    /// CHECK: Created, and closed again, by the handler.
+    #[account(mut, seeds = [UNDELEGATE_BUFFER_SEED, delegated_account.key().as_ref()], bump)]
+    pub undelegate_buffer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        close = payer,
+        seeds = [DELEGATION_RECORD_SEED, delegated_account.key().as_ref()],
+        bump
+    )]
+    pub delegation_record: Account<'info, DelegationRecord>,
+    #[account(
+        mut,
+        close = payer,
+        seeds = [DELEGATION_METADATA_SEED, delegated_account.key().as_ref()],
+        bump
+    )]
+    pub delegation_metadata: Account<'info, DelegationMetadata>,
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+pub struct DelegationRecord {
+    pub owner: Pubkey,
+    pub validator: Option<Pubkey>,
+    pub commit_frequency_ms: u32,
+    pub delegation_slot: u64,
+}
+
+impl DelegationRecord {
+    pub const SPACE: usize = 8 + 32 + 1 + 32 + 4 + 8;
+}
+
+#[account]
+pub struct DelegationMetadata {
+    pub seeds: Vec<Vec<u8>>, // The delegated account's PDA seeds, without the bump
+}
+
+impl DelegationMetadata {
+    pub fn space(seeds: &[Vec<u8>]) -> usize {
+        8 + 4 + seeds.iter().map(|seed| 4 + seed.len()).sum::<usize>()
+    }
+}
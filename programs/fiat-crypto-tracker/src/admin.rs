@@ -0,0 +1,357 @@
+// Admin changes to a feed and the optional council that must approve them.
+//
+// Every admin instruction is expressed as an `AdminAction`. While a feed has no
+// council its authority applies actions directly. Once a council is set the
+// direct instructions are rejected: a council member proposes the action in an
+// `AdminProposal` account, and anyone can execute it once `council_threshold`
+// members have approved. Only approvals from keys still on the council count,
+// so changing the council also changes which pending approvals are valid.
+
+use anchor_lang::prelude::*;
+use bytemuck::Zeroable;
+
+use crate::aggregation::AggregationMethod;
+use crate::staking::OracleStake;
+use crate::{
+    fixed_str, AuthorityTransferCancelled, AuthorityTransferProposed, CouncilUpdated, ErrorCode,
+    FeedStatus, FeedStatusChanged, Oracle, OracleAdded, OracleHaltChanged, OracleKeyReplaced,
+    OracleRemoved, OutlierPolicy, RateData, SettingsChanged, SlashApproved, DEFAULT_ORACLE_WEIGHT,
+    MAX_COUNCIL_MEMBERS, MAX_ORACLE_NAME_LEN,
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub enum AdminAction {
+    AddOracle { name: String, pubkey: Pubkey },
+    RemoveOracle { pubkey: Pubkey },
+    ReplaceOracleKey { old_pubkey: Pubkey, new_pubkey: Pubkey },
+    SetAggregationWindow { window_secs: i64 },
+    SetMaxStaleness { max_staleness_secs: i64 },
+    SetMaxDeviation { max_deviation_bps: u16 },
+    SetMinUpdateInterval { min_update_interval_secs: i64 },
+    SetOracleMinUpdateInterval { pubkey: Pubkey, min_update_interval_secs: Option<i64> },
+    SetOracleWeight { pubkey: Pubkey, weight: u16 },
+    SetAggregationMethod { method: AggregationMethod },
+    SetOutlierPolicy { policy: OutlierPolicy },
+    ApplyReputationWeights,
+    ConfigureStaking {
+        stake_mint: Pubkey,
+        treasury: Pubkey,
+        min_stake: u64,
+        unbonding_period_secs: i64,
+    },
+    ApproveSlash { pubkey: Pubkey, amount: u64 },
+    ConfigureRewards {
+        reward_mint: Pubkey,
+        reward_per_update: u64,
+        max_rewards_per_epoch: u64,
+    },
+    ProposeAuthority { new_authority: Pubkey },
+    SetStatus { status: FeedStatus },
+    SetOracleHalted { pubkey: Pubkey, halted: bool },
+    // An empty `members` list dissolves the council, handing control back to the authority.
+    SetCouncil { members: Vec<Pubkey>, threshold: u8 },
+    SetCommitPolicy { interval_secs: i64, every_updates: u32, deviation_bps: u16 },
+    CancelAuthorityTransfer,
+}
+
+impl AdminAction {
+    // Whether the action only changes the feed's settings. These are reported
+    // with a `SettingsChanged` event carrying the action itself.
+    pub fn changes_settings(&self) -> bool {
+        matches!(
+            self,
+            AdminAction::SetAggregationWindow { .. }
+                | AdminAction::SetMaxStaleness { .. }
+                | AdminAction::SetMaxDeviation { .. }
+                | AdminAction::SetMinUpdateInterval { .. }
+                | AdminAction::SetOracleMinUpdateInterval { .. }
+                | AdminAction::SetOracleWeight { .. }
+                | AdminAction::SetAggregationMethod { .. }
+                | AdminAction::SetOutlierPolicy { .. }
+                | AdminAction::ApplyReputationWeights
+                | AdminAction::ConfigureStaking { .. }
+                | AdminAction::ConfigureRewards { .. }
+                | AdminAction::SetCommitPolicy { .. }
+        )
+    }
+
+    // Applies the action at `now` to `rate_data`, which lives at `rate_data_key`.
+    // `ApproveSlash` also needs the slashed key's stake account.
+    pub fn apply(
+        self,
+        rate_data: &mut RateData,
+        rate_data_key: Pubkey,
+        now: i64,
+        oracle_stake: Option<&mut OracleStake>,
+    ) -> Result<()> {
+        let settings = self.changes_settings().then(|| self.clone());
+        match self {
+            AdminAction::AddOracle { name, pubkey } => {
+                require!(name.len() <= MAX_ORACLE_NAME_LEN, ErrorCode::OracleNameTooLong);
+
+                // Check if an oracle with the same public key already exists to prevent duplicates.
+                if rate_data.find_oracle(&pubkey).is_some() {
+                    return err!(ErrorCode::OracleAlreadyExists);
+                }
+
+                // Create and add the new oracle to the list.
+                let new_oracle = Oracle {
+                    name: fixed_str(&name),
+                    pubkey,
+                    rate: 0, // Initialize rate to 0
+                    last_updated: 0, // Initialize last updated timestamp to 0
+                    weight: DEFAULT_ORACLE_WEIGHT,
+                    ..Zeroable::zeroed()
+                };
+                rate_data.push_oracle(new_oracle)?;
+                msg!("Oracle {} with pubkey {} added.", name, pubkey);
+                emit!(OracleAdded { rate_data: rate_data_key, oracle: pubkey, name });
+            }
+            AdminAction::RemoveOracle { pubkey } => {
+                let index = rate_data
+                    .find_oracle(&pubkey)
+                    .ok_or(ErrorCode::OracleNotFound)?;
+                // Rewards it has not claimed go with the entry and stay in the vault.
+                let removed = rate_data.remove_oracle(index);
+                msg!("Oracle {} with pubkey {} removed.", removed.name(), pubkey);
+
+                // Drop the removed oracle's rate from the aggregate straight away.
+                rate_data.refresh_aggregate(now);
+                emit!(OracleRemoved {
+                    rate_data: rate_data_key,
+                    oracle: pubkey,
+                    forfeited_rewards: removed.accrued_rewards,
+                });
+            }
+            AdminAction::ReplaceOracleKey { old_pubkey, new_pubkey } => {
+                // The new key must not already belong to another oracle.
+                if rate_data.find_oracle(&new_pubkey).is_some() {
+                    return err!(ErrorCode::OracleAlreadyExists);
+                }
+
+                let index = rate_data
+                    .find_oracle(&old_pubkey)
+                    .ok_or(ErrorCode::OracleNotFound)?;
+
+                // Stake belongs to a key, so the new key starts out unstaked until
+                // `sync_stake` copies over any it already has.
+                let oracle = &mut rate_data.oracles[index];
+                oracle.pubkey = new_pubkey;
+                oracle.stake = 0;
+                msg!("Oracle {} key replaced: {} -> {}", oracle.name(), old_pubkey, new_pubkey);
+                emit!(OracleKeyReplaced { rate_data: rate_data_key, old_pubkey, new_pubkey });
+            }
+            AdminAction::SetAggregationWindow { window_secs } => {
+                require!(window_secs > 0, ErrorCode::InvalidAggregationWindow);
+                rate_data.aggregation_window_secs = window_secs;
+                msg!("Aggregation window set to {} seconds.", window_secs);
+            }
+            AdminAction::SetMaxStaleness { max_staleness_secs } => {
+                require!(max_staleness_secs > 0, ErrorCode::InvalidMaxStaleness);
+                rate_data.max_staleness_secs = max_staleness_secs;
+                msg!("Max staleness set to {} seconds.", max_staleness_secs);
+            }
+            AdminAction::SetMaxDeviation { max_deviation_bps } => {
+                rate_data.max_deviation_bps = max_deviation_bps;
+                msg!("Max deviation set to {} bps.", max_deviation_bps);
+            }
+            AdminAction::SetMinUpdateInterval { min_update_interval_secs } => {
+                require!(min_update_interval_secs >= 0, ErrorCode::InvalidUpdateInterval);
+                rate_data.min_update_interval_secs = min_update_interval_secs;
+                msg!("Min update interval set to {} seconds.", min_update_interval_secs);
+            }
+            AdminAction::SetOracleMinUpdateInterval { pubkey, min_update_interval_secs } => {
+                let index = rate_data
+                    .find_oracle(&pubkey)
+                    .ok_or(ErrorCode::OracleNotFound)?;
+                let oracle = &mut rate_data.oracles[index];
+                match min_update_interval_secs {
+                    Some(secs) => {
+                        require!(secs >= 0, ErrorCode::InvalidUpdateInterval);
+                        oracle.has_interval_override = 1;
+                        oracle.min_update_interval_secs = secs;
+                        msg!("Oracle {} min update interval set to {}s.", oracle.name(), secs);
+                    }
+                    None => {
+                        oracle.has_interval_override = 0;
+                        oracle.min_update_interval_secs = 0;
+                        msg!("Oracle {} uses the feed's min update interval.", oracle.name());
+                    }
+                }
+            }
+            AdminAction::SetOracleWeight { pubkey, weight } => {
+                let index = rate_data
+                    .find_oracle(&pubkey)
+                    .ok_or(ErrorCode::OracleNotFound)?;
+                rate_data.oracles[index].weight = weight;
+                msg!("Oracle {} weight set to {}.", rate_data.oracles[index].name(), weight);
+
+                rate_data.refresh_aggregate(now);
+            }
+            AdminAction::SetAggregationMethod { method } => {
+                method.validate()?;
+                (rate_data.aggregation_method, rate_data.trim_pct) = method.to_stored();
+                msg!("Aggregation method set to {:?}.", method);
+
+                rate_data.refresh_aggregate(now);
+            }
+            AdminAction::SetOutlierPolicy { policy } => {
+                rate_data.outlier_policy = policy as u8;
+                msg!("Outlier policy set to {:?}.", policy);
+            }
+            AdminAction::ApplyReputationWeights => {
+                let len = rate_data.num_oracles as usize;
+                for oracle in &mut rate_data.oracles[..len] {
+                    if let Some(weight) = oracle.reputation_weight() {
+                        oracle.weight = weight;
+                        msg!("Oracle {} weight set to {}.", oracle.name(), weight);
+                    }
+                }
+
+                rate_data.refresh_aggregate(now);
+            }
+            AdminAction::ConfigureStaking {
+                stake_mint,
+                treasury,
+                min_stake,
+                unbonding_period_secs,
+            } => {
+                require!(
+                    stake_mint == rate_data.stake_mint || rate_data.total_staked == 0,
+                    ErrorCode::StakeMintLocked
+                );
+                require!(unbonding_period_secs >= 0, ErrorCode::InvalidUnbondingPeriod);
+                rate_data.stake_mint = stake_mint;
+                rate_data.treasury = treasury;
+                rate_data.min_stake = min_stake;
+                rate_data.unbonding_period_secs = unbonding_period_secs;
+                msg!("Staking configured: min stake {} of mint {}.", min_stake, stake_mint);
+
+                // Oracles may have crossed the new minimum.
+                rate_data.refresh_aggregate(now);
+            }
+            AdminAction::ApproveSlash { pubkey, amount } => {
+                // The key needn't still be an oracle; its stake answers for its past rates.
+                let oracle_stake = oracle_stake.ok_or(ErrorCode::InvalidOracleStake)?;
+                require!(
+                    oracle_stake.rate_data == rate_data_key && oracle_stake.staker == pubkey,
+                    ErrorCode::InvalidOracleStake
+                );
+                oracle_stake.slash_approved = oracle_stake.slash_approved.saturating_add(amount);
+                emit!(SlashApproved { rate_data: rate_data_key, oracle: pubkey, amount });
+            }
+            AdminAction::ConfigureRewards {
+                reward_mint,
+                reward_per_update,
+                max_rewards_per_epoch,
+            } => {
+                let locked = rate_data.reward_mint != Pubkey::default();
+                require!(
+                    !locked || reward_mint == rate_data.reward_mint,
+                    ErrorCode::RewardMintLocked
+                );
+                rate_data.reward_mint = reward_mint;
+                rate_data.reward_per_update = reward_per_update;
+                rate_data.max_rewards_per_epoch = max_rewards_per_epoch;
+                msg!(
+                    "Rewards configured: {} per update, at most {} per epoch.",
+                    reward_per_update,
+                    max_rewards_per_epoch
+                );
+            }
+            AdminAction::ProposeAuthority { new_authority } => {
+                require_keys_neq!(new_authority, Pubkey::default(), ErrorCode::InvalidAuthority);
+                rate_data.pending_authority = new_authority;
+                emit!(AuthorityTransferProposed {
+                    rate_data: rate_data_key,
+                    authority: rate_data.authority,
+                    pending_authority: new_authority,
+                });
+            }
+            AdminAction::CancelAuthorityTransfer => {
+                let cancelled = rate_data.pending_authority;
+                require_keys_neq!(cancelled, Pubkey::default(), ErrorCode::NoPendingAuthority);
+                rate_data.pending_authority = Pubkey::default();
+                emit!(AuthorityTransferCancelled {
+                    rate_data: rate_data_key,
+                    authority: rate_data.authority,
+                    cancelled_authority: cancelled,
+                });
+            }
+            AdminAction::SetStatus { status } => {
+                rate_data.status = status as u8;
+                msg!("Feed status set to {:?}.", status);
+                emit!(FeedStatusChanged { rate_data: rate_data_key, status });
+            }
+            AdminAction::SetOracleHalted { pubkey, halted } => {
+                let index = rate_data
+                    .find_oracle(&pubkey)
+                    .ok_or(ErrorCode::OracleNotFound)?;
+                rate_data.oracles[index].halted = halted as u8;
+                msg!("Oracle {} halted: {}", rate_data.oracles[index].name(), halted);
+
+                // Drop (or restore) the oracle's rate in the aggregate straight away.
+                rate_data.refresh_aggregate(now);
+                emit!(OracleHaltChanged { rate_data: rate_data_key, oracle: pubkey, halted });
+            }
+            AdminAction::SetCouncil { members, threshold } => {
+                rate_data.set_council(&members, threshold)?;
+                emit!(CouncilUpdated { rate_data: rate_data_key, members, threshold });
+            }
+            AdminAction::SetCommitPolicy { interval_secs, every_updates, deviation_bps } => {
+                require!(interval_secs >= 0, ErrorCode::InvalidCommitInterval);
+                rate_data.commit_interval_secs = interval_secs;
+                rate_data.commit_every_updates = every_updates;
+                rate_data.commit_deviation_bps = deviation_bps;
+                msg!(
+                    "Commit policy set: every {}s, {} updates or {} bps.",
+                    interval_secs,
+                    every_updates,
+                    deviation_bps
+                );
+            }
+        }
+        if let Some(action) = settings {
+            emit!(SettingsChanged { rate_data: rate_data_key, action });
+        }
+        Ok(())
+    }
+}
+
+impl RateData {
+    // The current council members, empty if the feed has no council.
+    pub fn council(&self) -> &[Pubkey] {
+        &self.council[..self.council_size as usize]
+    }
+
+    pub fn has_council(&self) -> bool {
+        self.council_size > 0
+    }
+
+    pub fn is_council_member(&self, key: &Pubkey) -> bool {
+        self.council().contains(key)
+    }
+
+    // Replaces the council. Members must be distinct and the threshold must be
+    // reachable; dissolving the council requires a threshold of 0.
+    fn set_council(&mut self, members: &[Pubkey], threshold: u8) -> Result<()> {
+        let valid_threshold = if members.is_empty() {
+            threshold == 0
+        } else {
+            threshold > 0 && threshold as usize <= members.len()
+        };
+        require!(
+            valid_threshold
+                && members.len() <= MAX_COUNCIL_MEMBERS
+                && !members.contains(&Pubkey::default())
+                && members.iter().enumerate().all(|(i, m)| !members[..i].contains(m)),
+            ErrorCode::InvalidCouncil
+        );
+
+        self.council = [Pubkey::default(); MAX_COUNCIL_MEMBERS];
+        self.council[..members.len()].copy_from_slice(members);
+        self.council_size = members.len() as u8;
+        self.council_threshold = threshold;
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+// The statistics a feed can use to combine its oracles' rates into one aggregate.
+//
+// Every function takes the rates that are eligible to contribute (non-zero, fresh
+// and not halted) as `(rate, weight)` samples and returns `None` if there are none
+// left to aggregate. The weighted methods ignore samples with a weight of 0.
+
+use anchor_lang::prelude::*;
+
+use crate::ErrorCode;
+
+// How a feed combines its oracles' rates. Stored in `RateData` as the variant's
+// index in `aggregation_method` plus `trim_pct` for `TrimmedMean`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggregationMethod {
+    Median,
+    WeightedMedian,
+    WeightedMean,
+    // Mean of the rates left after dropping `trim_pct`% of them from each end.
+    TrimmedMean { trim_pct: u8 },
+    // The lowest rate, i.e. the bottom of the band the oracles report.
+    Min,
+    // The highest rate, i.e. the top of the band the oracles report.
+    Max,
+}
+
+impl AggregationMethod {
+    pub fn from_stored(method: u8, trim_pct: u8) -> Option<Self> {
+        match method {
+            0 => Some(AggregationMethod::Median),
+            1 => Some(AggregationMethod::WeightedMedian),
+            2 => Some(AggregationMethod::WeightedMean),
+            3 => Some(AggregationMethod::TrimmedMean { trim_pct }),
+            4 => Some(AggregationMethod::Min),
+            5 => Some(AggregationMethod::Max),
+            _ => None,
+        }
+    }
+
+    // The `(aggregation_method, trim_pct)` pair `from_stored` decodes.
+    pub fn to_stored(self) -> (u8, u8) {
+        match self {
+            AggregationMethod::Median => (0, 0),
+            AggregationMethod::WeightedMedian => (1, 0),
+            AggregationMethod::WeightedMean => (2, 0),
+            AggregationMethod::TrimmedMean { trim_pct } => (3, trim_pct),
+            AggregationMethod::Min => (4, 0),
+            AggregationMethod::Max => (5, 0),
+        }
+    }
+
+    // Trimming half or more from each end would leave nothing to average.
+    pub fn validate(self) -> Result<()> {
+        if let AggregationMethod::TrimmedMean { trim_pct } = self {
+            require!(trim_pct < 50, ErrorCode::InvalidTrimPercentage);
+        }
+        Ok(())
+    }
+
+    // Whether oracles' weights affect the aggregate.
+    pub fn is_weighted(self) -> bool {
+        matches!(self, AggregationMethod::WeightedMedian | AggregationMethod::WeightedMean)
+    }
+
+    pub fn aggregate(self, samples: &mut [(u64, u64)]) -> Option<u64> {
+        match self {
+            AggregationMethod::Median => median(samples),
+            AggregationMethod::WeightedMedian => weighted_median(samples),
+            AggregationMethod::WeightedMean => weighted_mean(samples),
+            AggregationMethod::TrimmedMean { trim_pct } => trimmed_mean(samples, trim_pct),
+            AggregationMethod::Min => samples.iter().map(|&(rate, _)| rate).min(),
+            AggregationMethod::Max => samples.iter().map(|&(rate, _)| rate).max(),
+        }
+    }
+}
+
+// Median of the samples' rates, averaging the two middle values for an even
+// count. Weights are ignored.
+pub fn median(samples: &mut [(u64, u64)]) -> Option<u64> {
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort_unstable_by_key(|&(rate, _)| rate);
+    let mid = samples.len() / 2;
+    if samples.len() % 2 == 1 {
+        Some(samples[mid].0)
+    } else {
+        let (lo, hi) = (samples[mid - 1].0, samples[mid].0);
+        Some(lo + (hi - lo) / 2)
+    }
+}
+
+// The rate at which half of the total weight lies on either side. If the weight
+// splits exactly between two rates they are averaged, so equal weights give the
+// same result as `median`.
+pub fn weighted_median(samples: &mut [(u64, u64)]) -> Option<u64> {
+    let total: u128 = samples.iter().map(|&(_, weight)| weight as u128).sum();
+    if total == 0 {
+        return None;
+    }
+    samples.sort_unstable_by_key(|&(rate, _)| rate);
+
+    let mut weighted = samples.iter().filter(|&&(_, weight)| weight > 0);
+    let mut cumulative = 0u128;
+    while let Some(&(rate, weight)) = weighted.next() {
+        cumulative += weight as u128;
+        if cumulative * 2 == total {
+            let &(next, _) = weighted.next()?;
+            return Some(rate + (next - rate) / 2);
+        }
+        if cumulative * 2 > total {
+            return Some(rate);
+        }
+    }
+    None
+}
+
+// The weight-averaged rate, rounded down.
+pub fn weighted_mean(samples: &[(u64, u64)]) -> Option<u64> {
+    let (sum, total) = samples.iter().fold((0u128, 0u128), |(sum, total), &(rate, weight)| {
+        (sum + rate as u128 * weight as u128, total + weight as u128)
+    });
+    if total == 0 {
+        return None;
+    }
+    // A weighted average never exceeds the largest rate, so it fits in a u64.
+    Some((sum / total) as u64)
+}
+
+// Mean of the rates left after dropping `trim_pct`% of the samples (rounded
+// down to whole samples) from each end, rounded down. Weights are ignored.
+pub fn trimmed_mean(samples: &mut [(u64, u64)], trim_pct: u8) -> Option<u64> {
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort_unstable_by_key(|&(rate, _)| rate);
+    let trim = samples.len() * trim_pct.min(49) as usize / 100;
+    let kept = &samples[trim..samples.len() - trim];
+    let sum: u128 = kept.iter().map(|&(rate, _)| rate as u128).sum();
+    Some((sum / kept.len() as u128) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Oracle, RateData};
+    use bytemuck::Zeroable;
+
+    const ALL: [AggregationMethod; 6] = [
+        AggregationMethod::Median,
+        AggregationMethod::WeightedMedian,
+        AggregationMethod::WeightedMean,
+        AggregationMethod::TrimmedMean { trim_pct: 25 },
+        AggregationMethod::Min,
+        AggregationMethod::Max,
+    ];
+
+    #[test]
+    fn one_oracle_is_its_own_aggregate() {
+        for method in ALL {
+            assert_eq!(method.aggregate(&mut [(145_025, 1)]), Some(145_025), "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn even_counts() {
+        let samples = [(140, 1), (160, 1), (150, 1), (100, 1)];
+        let aggregate = |method: AggregationMethod| method.aggregate(&mut samples.clone());
+        // The two middle rates are averaged.
+        assert_eq!(aggregate(AggregationMethod::Median), Some(145));
+        assert_eq!(aggregate(AggregationMethod::WeightedMedian), Some(145));
+        assert_eq!(aggregate(AggregationMethod::WeightedMean), Some(137));
+        // 25% of 4 drops one rate from each end.
+        assert_eq!(aggregate(AggregationMethod::TrimmedMean { trim_pct: 25 }), Some(145));
+        assert_eq!(aggregate(AggregationMethod::Min), Some(100));
+        assert_eq!(aggregate(AggregationMethod::Max), Some(160));
+    }
+
+    #[test]
+    fn trimmed_mean_drops_whole_samples_from_each_end() {
+        let mut samples = [(1, 1), (100, 1), (102, 1), (104, 1), (u64::MAX, 1)];
+        // 20% of 5 drops one rate from each end, so the outliers are ignored.
+        assert_eq!(trimmed_mean(&mut samples, 20), Some(102));
+        // 10% of 5 rounds down to no trimming at all.
+        assert_eq!(trimmed_mean(&mut [(100, 1), (101, 1), (103, 1)], 10), Some(101));
+        assert!(AggregationMethod::TrimmedMean { trim_pct: 50 }.validate().is_err());
+        assert!(AggregationMethod::TrimmedMean { trim_pct: 49 }.validate().is_ok());
+    }
+
+    #[test]
+    fn methods_round_trip_through_storage() {
+        for method in ALL {
+            let (stored, trim_pct) = method.to_stored();
+            assert_eq!(AggregationMethod::from_stored(stored, trim_pct), Some(method));
+        }
+        assert_eq!(AggregationMethod::from_stored(6, 0), None);
+    }
+
+    #[test]
+    fn all_stale_leaves_no_aggregate() {
+        let mut rate_data = RateData::zeroed();
+        rate_data.aggregation_window_secs = 300;
+        for (i, rate) in [145_000u64, 151_000].into_iter().enumerate() {
+            rate_data
+                .push_oracle(Oracle {
+                    pubkey: Pubkey::new_unique(),
+                    rate,
+                    last_updated: 1_000 + i as i64,
+                    weight: 1,
+                    ..Zeroable::zeroed()
+                })
+                .unwrap();
+        }
+
+        for method in ALL {
+            (rate_data.aggregation_method, rate_data.trim_pct) = method.to_stored();
+            rate_data.recompute_aggregate(1_000);
+            assert_eq!(rate_data.num_contributors, 2, "{:?}", method);
+
+            // Both oracles are now outside the aggregation window.
+            rate_data.recompute_aggregate(2_000);
+            assert_eq!(rate_data.aggregate_rate, 0, "{:?}", method);
+            assert_eq!(rate_data.num_contributors, 0, "{:?}", method);
+            assert_eq!(method.aggregate(&mut []), None, "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn the_aggregate_is_dated_by_its_newest_rate() {
+        let mut rate_data = RateData::zeroed();
+        rate_data.aggregation_window_secs = 300;
+        for last_updated in [1_000, 1_010] {
+            rate_data
+                .push_oracle(Oracle {
+                    pubkey: Pubkey::new_unique(),
+                    rate: 145_000,
+                    last_updated,
+                    weight: 1,
+                    ..Zeroable::zeroed()
+                })
+                .unwrap();
+        }
+
+        // Recomputing later, e.g. for an admin change, doesn't freshen it.
+        rate_data.recompute_aggregate(1_200);
+        assert_eq!(rate_data.aggregate_timestamp, 1_010);
+        // Nor does it lose the date once every rate has gone stale.
+        rate_data.recompute_aggregate(2_000);
+        assert_eq!(rate_data.aggregate_timestamp, 1_010);
+    }
+
+    #[test]
+    fn weighted_median_follows_the_weight() {
+        // The bank's weight outvotes both P2P rates.
+        assert_eq!(weighted_median(&mut [(150, 1), (145, 3), (152, 1)]), Some(145));
+        // Equal weights match the plain median, including the even-count average.
+        assert_eq!(weighted_median(&mut [(150, 2), (140, 2)]), Some(145));
+        assert_eq!(median(&mut [(150, 2), (140, 2)]), Some(145));
+        // Zero-weight samples do not count, even as the averaging neighbour.
+        assert_eq!(weighted_median(&mut [(140, 1), (141, 0), (150, 1)]), Some(145));
+        assert_eq!(weighted_median(&mut [(140, 0)]), None);
+    }
+
+    #[test]
+    fn weighted_mean_rounds_down() {
+        assert_eq!(weighted_mean(&[(100, 3), (200, 1)]), Some(125));
+        assert_eq!(weighted_mean(&[(100, 1), (101, 1)]), Some(100));
+        assert_eq!(weighted_mean(&[(u64::MAX, u64::MAX), (u64::MAX, 1)]), Some(u64::MAX));
+        assert_eq!(weighted_mean(&[]), None);
+    }
+}
@@ -0,0 +1,198 @@
+// Validated reads of a feed straight from its account, for programs that take
+// the `rate_data` account as an input rather than calling the tracker over CPI.
+//
+// `RateData::load_checked` verifies that the account is a migrated `RateData`
+// owned by this program at the PDA its own currency codes derive, then that the
+// aggregate is fresh enough and formed by enough oracles for the caller. It
+// only borrows the account, so it works from any program depending on this
+// crate with `no-entrypoint`.
+
+use std::cell::Ref;
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+use crate::fixed_point::deviation_bps;
+use crate::{ErrorCode, RateData, PAIR_SEPARATOR, RATE_DATA_SEED};
+
+// An aggregate that passed the caller's checks. `confidence_bps` is how far the
+// furthest contributing oracle sat from the aggregate, so a wide value means
+// the oracles disagreed.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CheckedRate {
+    pub rate: u64,
+    pub exponent: u8,
+    pub timestamp: i64,
+    pub num_contributors: u8,
+    pub confidence_bps: u64,
+}
+
+impl RateData {
+    // The feed's aggregate, failing unless it is at most `max_age` seconds old
+    // and formed by at least `min_oracles` oracles.
+    pub fn load_checked(
+        info: &AccountInfo,
+        max_age: i64,
+        min_oracles: u8,
+    ) -> Result<CheckedRate> {
+        let now = Clock::get()?.unix_timestamp;
+        Self::load_checked_at(info, now, max_age, min_oracles)
+    }
+
+    // `load_checked` as of `now`.
+    pub fn load_checked_at(
+        info: &AccountInfo,
+        now: i64,
+        max_age: i64,
+        min_oracles: u8,
+    ) -> Result<CheckedRate> {
+        let rate_data = Self::load_verified(info)?;
+        require!(!rate_data.is_paused(), ErrorCode::FeedPaused);
+        require!(
+            now.saturating_sub(rate_data.aggregate_timestamp) <= max_age,
+            ErrorCode::StaleRate
+        );
+        require!(
+            rate_data.num_contributors > 0 && rate_data.num_contributors >= min_oracles,
+            ErrorCode::NotEnoughOracles
+        );
+
+        // The aggregate is dated by its newest rate, so older contributors are
+        // only known to be the ones still contributing now.
+        let aggregate = rate_data.aggregate_rate;
+        let confidence_bps = rate_data
+            .active_oracles()
+            .iter()
+            .filter(|oracle| rate_data.contributes(oracle, now))
+            .map(|oracle| deviation_bps(oracle.rate, aggregate))
+            .max()
+            .unwrap_or(0);
+
+        Ok(CheckedRate {
+            rate: aggregate,
+            exponent: rate_data.exponent,
+            timestamp: rate_data.aggregate_timestamp,
+            num_contributors: rate_data.num_contributors,
+            confidence_bps,
+        })
+    }
+
+    // Borrows `info` as a `RateData`, checking its owner, discriminator, layout
+    // and address.
+    pub fn load_verified<'a>(info: &'a AccountInfo) -> Result<Ref<'a, RateData>> {
+        require_keys_eq!(
+            *info.owner,
+            crate::ID,
+            anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram
+        );
+        let data = info.try_borrow_data()?;
+        require!(
+            data.len() >= 8 && &data[..8] == RateData::DISCRIMINATOR,
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+        );
+        // Legacy feeds share the discriminator but not the size.
+        require!(
+            data.len() == RateData::SPACE,
+            anchor_lang::error::ErrorCode::AccountDidNotDeserialize
+        );
+        let rate_data = Ref::map(data, |data| bytemuck::from_bytes::<RateData>(&data[8..]));
+
+        let seeds: &[&[u8]] = &[
+            RATE_DATA_SEED,
+            rate_data.base().as_bytes(),
+            PAIR_SEPARATOR,
+            rate_data.quote().as_bytes(),
+            &[rate_data.bump],
+        ];
+        let expected = Pubkey::create_program_address(seeds, &crate::ID)
+            .map_err(|_| error!(anchor_lang::error::ErrorCode::ConstraintSeeds))?;
+        require_keys_eq!(*info.key, expected, anchor_lang::error::ErrorCode::ConstraintSeeds);
+        Ok(rate_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixed_str, Oracle};
+    use anchor_lang::error::ErrorCode as AnchorErrorCode;
+    use bytemuck::Zeroable;
+
+    // A USD/NGN feed whose two oracles reported 1450.00 and 1460.00 at 1_000.
+    fn feed() -> (Pubkey, Vec<u64>) {
+        let (key, bump) = Pubkey::find_program_address(
+            &[RATE_DATA_SEED, b"USD", PAIR_SEPARATOR, b"NGN"],
+            &crate::ID,
+        );
+        let mut rate_data = RateData::zeroed();
+        rate_data.base = fixed_str("USD");
+        rate_data.quote = fixed_str("NGN");
+        rate_data.bump = bump;
+        rate_data.exponent = 2;
+        rate_data.aggregation_window_secs = 300;
+        for rate in [145_000, 146_000] {
+            rate_data
+                .push_oracle(Oracle {
+                    pubkey: Pubkey::new_unique(),
+                    rate,
+                    last_updated: 1_000,
+                    weight: 1,
+                    ..Zeroable::zeroed()
+                })
+                .unwrap();
+        }
+        rate_data.recompute_aggregate(1_000);
+
+        // Backed by u64s so the data is aligned like a real account's.
+        let mut data = vec![0u64; RateData::SPACE / 8];
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut data);
+        bytes[..8].copy_from_slice(RateData::DISCRIMINATOR);
+        bytes[8..].copy_from_slice(bytemuck::bytes_of(&rate_data));
+        (key, data)
+    }
+
+    // Loads the account `key`, owned by `owner`, with a max age of 60 seconds.
+    fn load(
+        (key, owner): (Pubkey, Pubkey),
+        data: &mut [u64],
+        now: i64,
+        min_oracles: u8,
+    ) -> Result<CheckedRate> {
+        let mut lamports = 0;
+        let data: &mut [u8] = bytemuck::cast_slice_mut(data);
+        let info = AccountInfo::new(&key, false, false, &mut lamports, data, &owner, false, 0);
+        RateData::load_checked_at(&info, now, 60, min_oracles)
+    }
+
+    #[test]
+    fn returns_a_fresh_aggregate_with_its_spread() {
+        let (key, mut data) = feed();
+        let rate = load((key, crate::ID), &mut data, 1_030, 2).unwrap();
+        assert_eq!((rate.rate, rate.exponent, rate.num_contributors), (145_500, 2, 2));
+        // Both oracles sit 500 / 145_500 = 34 bps from the median.
+        assert_eq!(rate.confidence_bps, 34);
+    }
+
+    #[test]
+    fn rejects_stale_or_thin_aggregates() {
+        let (key, mut data) = feed();
+        let stale = load((key, crate::ID), &mut data, 1_061, 1).unwrap_err();
+        assert_eq!(stale, ErrorCode::StaleRate.into());
+        let thin = load((key, crate::ID), &mut data, 1_000, 3).unwrap_err();
+        assert_eq!(thin, ErrorCode::NotEnoughOracles.into());
+    }
+
+    #[test]
+    fn rejects_accounts_that_are_not_the_feed() {
+        let (key, mut data) = feed();
+        let other = Pubkey::new_unique();
+        let foreign = load((key, other), &mut data, 1_000, 1).unwrap_err();
+        assert_eq!(foreign, AnchorErrorCode::AccountOwnedByWrongProgram.into());
+        let wrong_address = load((other, crate::ID), &mut data, 1_000, 1).unwrap_err();
+        assert_eq!(wrong_address, AnchorErrorCode::ConstraintSeeds.into());
+
+        data[0] ^= 1;
+        let not_rate_data = load((key, crate::ID), &mut data, 1_000, 1).unwrap_err();
+        assert_eq!(not_rate_data, AnchorErrorCode::AccountDiscriminatorMismatch.into());
+    }
+}
@@ -0,0 +1,102 @@
+// When a delegated feed commits itself back to the base layer.
+//
+// While a feed is delegated to an ephemeral rollup, base-layer readers only see
+// what the rollup has committed: every `commit_frequency_ms` given at delegation,
+// and whenever someone calls `commit_rate_data`. A feed's authority can also
+// have `update_rate` schedule the commit itself once `commit_interval_secs` have
+// passed since the last one, once `commit_every_updates` updates have been
+// accepted, or once the aggregate has moved `commit_deviation_bps` from the last
+// committed one, whichever comes first. Each trigger is off while it is 0.
+//
+// Every commit scheduled by this program records its time in
+// `last_commit_timestamp` before the accounts are written back, so readers on
+// the base layer can tell how far behind the rollup their copy may be.
+
+use crate::fixed_point::deviation_bps;
+use crate::RateData;
+
+impl RateData {
+    // Whether the feed's commit policy calls for a commit at `now`.
+    pub fn commit_due(&self, now: i64) -> bool {
+        let interval = self.commit_interval_secs > 0
+            && now.saturating_sub(self.last_commit_timestamp) >= self.commit_interval_secs;
+        let updates = self.commit_every_updates > 0
+            && self.updates_since_commit >= self.commit_every_updates;
+        let deviation = self.commit_deviation_bps > 0
+            && deviation_bps(self.aggregate_rate, self.last_commit_rate)
+                >= self.commit_deviation_bps as u64;
+        interval || updates || deviation
+    }
+
+    // Restarts every trigger from a commit at `now`.
+    pub fn record_commit(&mut self, now: i64) {
+        self.last_commit_timestamp = now;
+        self.last_commit_rate = self.aggregate_rate;
+        self.updates_since_commit = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::Zeroable;
+
+    fn feed(interval_secs: i64, every_updates: u32, deviation_bps: u16) -> RateData {
+        let mut rate_data = RateData::zeroed();
+        rate_data.commit_interval_secs = interval_secs;
+        rate_data.commit_every_updates = every_updates;
+        rate_data.commit_deviation_bps = deviation_bps;
+        rate_data.aggregate_rate = 150_000;
+        rate_data.record_commit(1_000);
+        rate_data
+    }
+
+    #[test]
+    fn never_commits_without_a_policy() {
+        let mut rate_data = feed(0, 0, 0);
+        rate_data.updates_since_commit = 1_000;
+        rate_data.aggregate_rate = 300_000;
+        assert!(!rate_data.commit_due(1_000_000));
+    }
+
+    #[test]
+    fn commits_on_each_trigger() {
+        let rate_data = feed(60, 0, 0);
+        assert!(!rate_data.commit_due(1_059));
+        assert!(rate_data.commit_due(1_060));
+
+        let mut rate_data = feed(0, 3, 0);
+        rate_data.updates_since_commit = 2;
+        assert!(!rate_data.commit_due(1_000));
+        rate_data.updates_since_commit = 3;
+        assert!(rate_data.commit_due(1_000));
+
+        // 100 bps of the last committed 150_000 is 1_500, in either direction.
+        let mut rate_data = feed(0, 0, 100);
+        rate_data.aggregate_rate = 151_499;
+        assert!(!rate_data.commit_due(1_000));
+        rate_data.aggregate_rate = 148_500;
+        assert!(rate_data.commit_due(1_000));
+    }
+
+    #[test]
+    fn a_commit_restarts_every_trigger() {
+        let mut rate_data = feed(60, 3, 100);
+        rate_data.updates_since_commit = 3;
+        rate_data.aggregate_rate = 160_000;
+        assert!(rate_data.commit_due(1_060));
+
+        rate_data.record_commit(1_060);
+        assert_eq!(rate_data.last_commit_rate, 160_000);
+        assert!(!rate_data.commit_due(1_119));
+    }
+
+    #[test]
+    fn commits_the_first_aggregate() {
+        let mut rate_data = RateData::zeroed();
+        rate_data.commit_deviation_bps = 100;
+        assert!(!rate_data.commit_due(1_000));
+        rate_data.aggregate_rate = 150_000;
+        assert!(rate_data.commit_due(1_000));
+    }
+}
@@ -0,0 +1,354 @@
+// Delegating a feed to an ephemeral rollup and taking it back.
+//
+// `delegate_rate_data` hands a feed's `rate_data` and `rate_history` PDAs to the
+// delegation program. While delegated they are locked on the base layer and a
+// rollup validator clones them, so `update_rate` runs there cheaply and without
+// waiting for base-layer blocks. `commit_rate_data`, run on the rollup, asks the
+// rollup's magic program to write the current state back to the base layer;
+// `undelegate_rate_data` does the same and then has the delegation program
+// return both accounts to this program through `process_undelegation`.
+//
+// The accounts and instructions follow MagicBlock's delegation and magic
+// programs. They are built by hand here because `ephemeral-rollups-sdk` needs a
+// newer Anchor than this program uses.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::system_program;
+
+use crate::{CommitScheduled, RateData, RateHistory};
+
+pub const DELEGATION_PROGRAM_ID: Pubkey = pubkey!("DELeGGvXpWV2fqJUhqcF5ZSYMS4JTLjteaAMARRSaeSh");
+// Only exist on rollup validators.
+pub const MAGIC_PROGRAM_ID: Pubkey = pubkey!("Magic11111111111111111111111111111111111111");
+pub const MAGIC_CONTEXT_ID: Pubkey = pubkey!("MagicContext1111111111111111111111111111111");
+
+// Seeds of the accounts delegation uses, each followed by the delegated
+// account's address. The buffer is this program's, the rest the delegation
+// program's.
+pub const DELEGATE_BUFFER_SEED: &[u8] = b"buffer";
+pub const DELEGATION_RECORD_SEED: &[u8] = b"delegation";
+pub const DELEGATION_METADATA_SEED: &[u8] = b"delegation-metadata";
+pub const UNDELEGATE_BUFFER_SEED: &[u8] = b"undelegate-buffer";
+
+// The delegation program's `Delegate` instruction.
+const DELEGATE_DISCRIMINATOR: [u8; 8] = [0; 8];
+// The magic program's `ScheduleCommit` and `ScheduleCommitAndUndelegate`
+// instructions, as bincode-encoded enum variants.
+const SCHEDULE_COMMIT: [u8; 4] = [1, 0, 0, 0];
+const SCHEDULE_COMMIT_AND_UNDELEGATE: [u8; 4] = [2, 0, 0, 0];
+
+// Arguments of the delegation program's `Delegate` instruction.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DelegateAccountArgs {
+    pub commit_frequency_ms: u32, // How often the rollup commits the account on its own
+    pub seeds: Vec<Vec<u8>>,      // The account's PDA seeds, without the bump
+    pub validator: Option<Pubkey>, // Rollup validator the account is delegated to, any if None
+}
+
+// The delegation program's `Delegate` instruction, signed by the delegated account.
+pub fn delegate_instruction(
+    payer: Pubkey,
+    delegated_account: Pubkey,
+    buffer: Pubkey,
+    delegation_record: Pubkey,
+    delegation_metadata: Pubkey,
+    args: &DelegateAccountArgs,
+) -> Result<Instruction> {
+    let mut data = DELEGATE_DISCRIMINATOR.to_vec();
+    args.serialize(&mut data)?;
+    Ok(Instruction {
+        program_id: DELEGATION_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(delegated_account, true),
+            AccountMeta::new_readonly(crate::ID, false),
+            AccountMeta::new(buffer, false),
+            AccountMeta::new(delegation_record, false),
+            AccountMeta::new(delegation_metadata, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ],
+        data,
+    })
+}
+
+// The magic program's instruction to commit `accounts` to the base layer, and
+// then to undelegate them if `undelegate` is set.
+pub fn schedule_commit_instruction(
+    payer: Pubkey,
+    accounts: &[Pubkey],
+    undelegate: bool,
+) -> Instruction {
+    let mut metas = vec![AccountMeta::new(payer, true), AccountMeta::new(MAGIC_CONTEXT_ID, false)];
+    metas.extend(accounts.iter().map(|account| AccountMeta::new(*account, false)));
+    let data = if undelegate { SCHEDULE_COMMIT_AND_UNDELEGATE } else { SCHEDULE_COMMIT };
+    Instruction { program_id: MAGIC_PROGRAM_ID, accounts: metas, data: data.to_vec() }
+}
+
+// What `delegate_account` needs to delegate one PDA of this program.
+pub struct DelegateAccounts<'a, 'info> {
+    pub payer: &'a AccountInfo<'info>,
+    pub account: &'a AccountInfo<'info>,
+    pub buffer: &'a AccountInfo<'info>,
+    pub delegation_record: &'a AccountInfo<'info>,
+    pub delegation_metadata: &'a AccountInfo<'info>,
+    pub owner_program: &'a AccountInfo<'info>,
+    pub delegation_program: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+// Delegates `accounts.account`, the PDA with `seeds` and `bump`. Its data is
+// parked in the buffer PDA (bump `buffer_bump`) while it is emptied and assigned
+// to the delegation program, which then restores it from the buffer.
+pub fn delegate_account(
+    accounts: &DelegateAccounts,
+    seeds: &[&[u8]],
+    bump: u8,
+    buffer_bump: u8,
+    commit_frequency_ms: u32,
+    validator: Option<Pubkey>,
+) -> Result<()> {
+    let account = accounts.account;
+    let buffer = accounts.buffer;
+    let bump = [bump];
+    let account_seeds = [seeds, &[&bump[..]]].concat();
+    let buffer_bump = [buffer_bump];
+    let buffer_seeds: &[&[u8]] = &[DELEGATE_BUFFER_SEED, account.key.as_ref(), &buffer_bump];
+
+    let len = account.data_len();
+    create_pda(buffer, len, 0, buffer_seeds, accounts.payer, accounts.system_program)?;
+    {
+        let mut data = account.try_borrow_mut_data()?;
+        buffer.try_borrow_mut_data()?.copy_from_slice(&data);
+        data.fill(0);
+    }
+
+    // Only the system program can hand an account to another program.
+    account.assign(&system_program::ID);
+    system_program::assign(
+        CpiContext::new_with_signer(
+            accounts.system_program.clone(),
+            system_program::Assign { account_to_assign: account.clone() },
+            &[&account_seeds],
+        ),
+        &DELEGATION_PROGRAM_ID,
+    )?;
+
+    let args = DelegateAccountArgs {
+        commit_frequency_ms,
+        seeds: seeds.iter().map(|seed| seed.to_vec()).collect(),
+        validator,
+    };
+    let instruction = delegate_instruction(
+        accounts.payer.key(),
+        account.key(),
+        buffer.key(),
+        accounts.delegation_record.key(),
+        accounts.delegation_metadata.key(),
+        &args,
+    )?;
+    invoke_signed(
+        &instruction,
+        &[
+            accounts.payer.clone(),
+            account.clone(),
+            accounts.owner_program.clone(),
+            buffer.clone(),
+            accounts.delegation_record.clone(),
+            accounts.delegation_metadata.clone(),
+            accounts.system_program.clone(),
+            accounts.delegation_program.clone(),
+        ],
+        &[&account_seeds],
+    )?;
+
+    close_pda(buffer, accounts.payer)
+}
+
+// Re-creates the PDA of this program with `seeds` from the delegation program's
+// undelegation buffer, which holds the data it was last committed with.
+pub fn undelegate_account<'info>(
+    account: &AccountInfo<'info>,
+    buffer: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    seeds: &[Vec<u8>],
+) -> Result<()> {
+    let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+    let (expected, bump) = Pubkey::find_program_address(&seeds, &crate::ID);
+    require_keys_eq!(account.key(), expected, anchor_lang::error::ErrorCode::ConstraintSeeds);
+    let bump = [bump];
+    let account_seeds = [&seeds[..], &[&bump[..]]].concat();
+
+    let len = buffer.data_len();
+    let rent = Rent::get()?.minimum_balance(len);
+    create_pda(account, len, rent, &account_seeds, payer, system_program)?;
+    account.try_borrow_mut_data()?.copy_from_slice(&buffer.try_borrow_data()?);
+    Ok(())
+}
+
+// Asks the rollup to commit `accounts` to the base layer, and then to
+// undelegate them if `undelegate` is set. Anything held in an `Account` must be
+// written back with `exit` first.
+pub fn commit_accounts<'info>(
+    payer: &AccountInfo<'info>,
+    magic_context: &AccountInfo<'info>,
+    magic_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    undelegate: bool,
+) -> Result<()> {
+    let keys: Vec<Pubkey> = accounts.iter().map(|account| account.key()).collect();
+    let instruction = schedule_commit_instruction(payer.key(), &keys, undelegate);
+    let mut infos = vec![payer.clone(), magic_context.clone(), magic_program.clone()];
+    infos.extend_from_slice(accounts);
+    invoke(&instruction, &infos)?;
+    Ok(())
+}
+
+// Commits a feed's rate data and history, recording the commit in the rate data
+// first so that the copy written back carries it. The magic program reads the
+// accounts when the commit is scheduled, so the history is written out early.
+pub fn commit_feed<'info>(
+    rate_data: &AccountLoader<'info, RateData>,
+    rate_history: &Account<'info, RateHistory>,
+    payer: &AccountInfo<'info>,
+    magic_context: &AccountInfo<'info>,
+    magic_program: &AccountInfo<'info>,
+    undelegate: bool,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let aggregate_rate = {
+        let mut rate_data = rate_data.load_mut()?;
+        rate_data.record_commit(now);
+        rate_data.aggregate_rate
+    };
+    rate_history.exit(&crate::ID)?;
+    commit_accounts(
+        payer,
+        magic_context,
+        magic_program,
+        &[rate_data.to_account_info(), rate_history.to_account_info()],
+        undelegate,
+    )?;
+    emit!(CommitScheduled { rate_data: rate_data.key(), aggregate_rate, timestamp: now });
+    Ok(())
+}
+
+// Creates `target`, a PDA of this program with `seeds`, holding `len` bytes and
+// `lamports`. Anyone can send lamports to the address beforehand, so that case
+// tops the balance up and allocates and assigns the account instead.
+fn create_pda<'info>(
+    target: &AccountInfo<'info>,
+    len: usize,
+    lamports: u64,
+    seeds: &[&[u8]],
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let system = system_program.clone();
+    if target.lamports() == 0 {
+        return system_program::create_account(
+            CpiContext::new_with_signer(
+                system,
+                system_program::CreateAccount { from: payer.clone(), to: target.clone() },
+                &[seeds],
+            ),
+            lamports,
+            len as u64,
+            &crate::ID,
+        );
+    }
+
+    let top_up = lamports.saturating_sub(target.lamports());
+    if top_up > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system.clone(),
+                system_program::Transfer { from: payer.clone(), to: target.clone() },
+            ),
+            top_up,
+        )?;
+    }
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            system.clone(),
+            system_program::Allocate { account_to_allocate: target.clone() },
+            &[seeds],
+        ),
+        len as u64,
+    )?;
+    system_program::assign(
+        CpiContext::new_with_signer(
+            system,
+            system_program::Assign { account_to_assign: target.clone() },
+            &[seeds],
+        ),
+        &crate::ID,
+    )
+}
+
+// Closes `account`, a PDA of this program, refunding its lamports to `to`.
+fn close_pda<'info>(account: &AccountInfo<'info>, to: &AccountInfo<'info>) -> Result<()> {
+    **to.try_borrow_mut_lamports()? += account.lamports();
+    **account.try_borrow_mut_lamports()? = 0;
+    account.resize(0)?;
+    account.assign(&system_program::ID);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::ProcessUndelegation;
+    use anchor_lang::Discriminator;
+
+    #[test]
+    fn encodes_the_delegate_instruction() {
+        let (payer, account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let record_seeds: &[&[u8]] = &[DELEGATION_RECORD_SEED, account.as_ref()];
+        let record = Pubkey::find_program_address(record_seeds, &DELEGATION_PROGRAM_ID).0;
+        let args = DelegateAccountArgs {
+            commit_frequency_ms: 30_000,
+            seeds: vec![b"rate_data".to_vec(), b"USD".to_vec()],
+            validator: None,
+        };
+        let (buffer, metadata) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let ix = delegate_instruction(payer, account, buffer, record, metadata, &args).unwrap();
+
+        assert_eq!(ix.program_id, DELEGATION_PROGRAM_ID);
+        assert_eq!(&ix.data[..8], &[0; 8]);
+        assert_eq!(DelegateAccountArgs::try_from_slice(&ix.data[8..]).unwrap(), args);
+        // u32 frequency, then two length-prefixed seeds, then `None`.
+        assert_eq!(ix.data.len(), 8 + 4 + 4 + (4 + 9) + (4 + 3) + 1);
+
+        let signers: Vec<_> =
+            ix.accounts.iter().filter(|meta| meta.is_signer).map(|meta| meta.pubkey).collect();
+        assert_eq!(signers, [payer, account]);
+        assert_eq!(ix.accounts[2].pubkey, crate::ID);
+        assert_eq!(ix.accounts[4].pubkey, record);
+        assert!(!ix.accounts[6].is_writable);
+    }
+
+    #[test]
+    fn encodes_scheduled_commits() {
+        let payer = Pubkey::new_unique();
+        let accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+
+        let commit = schedule_commit_instruction(payer, &accounts, false);
+        assert_eq!(commit.program_id, MAGIC_PROGRAM_ID);
+        assert_eq!(commit.data, [1, 0, 0, 0]);
+        let keys: Vec<_> = commit.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys, [payer, MAGIC_CONTEXT_ID, accounts[0], accounts[1]]);
+        assert!(commit.accounts.iter().all(|meta| meta.is_writable));
+        assert!(commit.accounts[0].is_signer && !commit.accounts[2].is_signer);
+
+        assert_eq!(schedule_commit_instruction(payer, &accounts, true).data, [2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn answers_the_delegation_programs_undelegation_callback() {
+        // The delegation program calls back with this fixed discriminator.
+        assert_eq!(ProcessUndelegation::DISCRIMINATOR, [196, 28, 41, 206, 48, 37, 51, 167]);
+    }
+}
@@ -0,0 +1,156 @@
+// Helpers for working with fixed-point rates.
+//
+// A feed stores its rate as an integer scaled by `10^exponent`, so with an
+// exponent of 2 a stored rate of 145025 means 1 base = 1450.25 quote. All
+// intermediate math is done in u128 and fails with `MathOverflow` instead of
+// wrapping or truncating the result.
+
+use anchor_lang::prelude::*;
+
+use crate::ErrorCode;
+
+// 10^19 no longer fits in a u64, so larger exponents could never be applied.
+pub const MAX_EXPONENT: u8 = 18;
+
+fn pow10(exp: u32) -> Result<u128> {
+    10u128.checked_pow(exp).ok_or_else(|| error!(ErrorCode::MathOverflow))
+}
+
+// Multiplies `value` by 10^`shift`, or divides (rounding down) for a negative shift.
+fn shift_decimals(value: u128, shift: i32) -> Result<u128> {
+    if shift >= 0 {
+        value
+            .checked_mul(pow10(shift as u32)?)
+            .ok_or_else(|| error!(ErrorCode::MathOverflow))
+    } else {
+        Ok(value / pow10(shift.unsigned_abs())?)
+    }
+}
+
+fn to_u64(value: u128) -> Result<u64> {
+    u64::try_from(value).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+// Converts an amount of the base currency into the quote currency at `rate`.
+// `amount` has `amount_decimals` decimals and the result has `out_decimals`.
+// The result is rounded down.
+pub fn base_to_quote(
+    amount: u64,
+    amount_decimals: u8,
+    rate: u64,
+    exponent: u8,
+    out_decimals: u8,
+) -> Result<u64> {
+    let product = amount as u128 * rate as u128;
+    let shift = out_decimals as i32 - amount_decimals as i32 - exponent as i32;
+    to_u64(shift_decimals(product, shift)?)
+}
+
+// Converts an amount of the quote currency into the base currency at `rate`.
+// `amount` has `amount_decimals` decimals and the result has `out_decimals`.
+// The result is rounded down.
+pub fn quote_to_base(
+    amount: u64,
+    amount_decimals: u8,
+    rate: u64,
+    exponent: u8,
+    out_decimals: u8,
+) -> Result<u64> {
+    require!(rate != 0, ErrorCode::MathOverflow);
+    let shift = out_decimals as i32 + exponent as i32 - amount_decimals as i32;
+    // Scale up before dividing so no precision is lost to the division.
+    let (numerator, post_shift) = if shift >= 0 {
+        (shift_decimals(amount as u128, shift)?, 0)
+    } else {
+        (amount as u128, shift)
+    };
+    to_u64(shift_decimals(numerator / rate as u128, post_shift)?)
+}
+
+// Re-expresses `rate` with a different exponent, rounding down when precision is dropped.
+pub fn rescale(rate: u64, exponent: u8, new_exponent: u8) -> Result<u64> {
+    to_u64(shift_decimals(rate as u128, new_exponent as i32 - exponent as i32)?)
+}
+
+// How far `rate` is from `reference`, in basis points of `reference`, rounded
+// down. Saturates rather than overflowing for extreme rates.
+pub fn deviation_bps(rate: u64, reference: u64) -> u64 {
+    if reference == 0 {
+        return if rate == 0 { 0 } else { u64::MAX };
+    }
+    let diff = rate.abs_diff(reference) as u128;
+    u64::try_from(diff * 10_000 / reference as u128).unwrap_or(u64::MAX)
+}
+
+// The signed change from `old` to `new`, in basis points of `old`, rounded
+// towards zero. 0 if there was no previous rate to compare against.
+pub fn change_bps(old: u64, new: u64) -> i64 {
+    if old == 0 {
+        return 0;
+    }
+    let change = (new as i128 - old as i128) * 10_000 / old as i128;
+    change.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+// Renders a scaled rate as a decimal string for logs, e.g. (145025, 2) -> "1450.25".
+pub fn format_scaled(rate: u64, exponent: u8) -> String {
+    if exponent == 0 {
+        return rate.to_string();
+    }
+    let divisor = 10u128.pow(exponent as u32);
+    let whole = rate as u128 / divisor;
+    let frac = rate as u128 % divisor;
+    format!("{}.{:0width$}", whole, frac, width = exponent as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_to_quote_applies_exponent() {
+        // 2.5 USD (6 decimals) at 1450.25 NGN/USD -> 3625.625 NGN, rounded down at 2 decimals.
+        assert_eq!(base_to_quote(2_500_000, 6, 145_025, 2, 2).unwrap(), 362_562);
+    }
+
+    #[test]
+    fn base_to_quote_does_not_overflow_intermediates() {
+        // u64::MAX * u64::MAX does not fit in a u64 but the scaled result does.
+        assert_eq!(base_to_quote(u64::MAX, 18, u64::MAX, 18, 0).unwrap(), 340);
+        assert!(base_to_quote(u64::MAX, 0, u64::MAX, 0, 0).is_err());
+    }
+
+    #[test]
+    fn quote_to_base_inverts_rate() {
+        // 3625.62 NGN at 1450.25 NGN/USD -> 2.499996 USD at 6 decimals.
+        assert_eq!(quote_to_base(362_562, 2, 145_025, 2, 6).unwrap(), 2_499_996);
+        assert!(quote_to_base(1, 0, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn deviation_is_relative_to_reference() {
+        assert_eq!(deviation_bps(10_500, 10_000), 500);
+        assert_eq!(deviation_bps(9_500, 10_000), 500);
+        assert_eq!(deviation_bps(10_000, 10_000), 0);
+        assert_eq!(deviation_bps(0, 10_000), 10_000);
+        assert_eq!(deviation_bps(u64::MAX, 1), u64::MAX);
+    }
+
+    #[test]
+    fn change_is_signed() {
+        assert_eq!(change_bps(10_000, 10_500), 500);
+        assert_eq!(change_bps(10_000, 9_500), -500);
+        assert_eq!(change_bps(100_000, 99_999), 0);
+        assert_eq!(change_bps(0, 10_000), 0);
+        assert_eq!(change_bps(1, u64::MAX), i64::MAX);
+    }
+
+    #[test]
+    fn rescale_and_format() {
+        assert_eq!(rescale(145_025, 2, 4).unwrap(), 14_502_500);
+        assert_eq!(rescale(145_025, 2, 0).unwrap(), 1450);
+        assert_eq!(format_scaled(145_025, 2), "1450.25");
+        assert_eq!(format_scaled(5, 3), "0.005");
+        assert_eq!(format_scaled(1450, 0), "1450");
+    }
+}
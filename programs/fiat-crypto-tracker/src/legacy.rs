@@ -0,0 +1,84 @@
+// The Borsh layout `RateData` had before it became zero-copy, kept only so that
+// `migrate_rate_data` can read existing accounts. Both layouts share the same
+// discriminator, so legacy accounts are told apart by their size: a migrated
+// account is always exactly `RateData::SPACE` bytes, which no legacy account
+// (127 + 84 * oracles bytes) can be.
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use bytemuck::Zeroable;
+
+use crate::{fixed_str, ErrorCode, Oracle, RateData, DEFAULT_ORACLE_WEIGHT, MAX_ORACLES};
+
+#[derive(AnchorDeserialize)]
+pub struct LegacyRateData {
+    pub authority: Pubkey,
+    pub base: String,
+    pub quote: String,
+    pub bump: u8,
+    pub exponent: u8,
+    pub aggregate_rate: u64,
+    pub num_contributors: u8,
+    pub aggregate_timestamp: i64,
+    pub aggregation_window_secs: i64,
+    pub max_staleness_secs: i64,
+    pub price_cumulative: u64,
+    pub live_secs_cumulative: u64,
+    pub cumulative_timestamp: i64,
+    pub oracles: Vec<LegacyOracle>,
+}
+
+#[derive(AnchorDeserialize)]
+pub struct LegacyOracle {
+    pub name: String,
+    pub pubkey: Pubkey,
+    pub rate: u64,
+    pub last_updated: i64,
+}
+
+impl LegacyRateData {
+    // Decodes a legacy account, failing if it has already been migrated.
+    pub fn try_from_account(info: &AccountInfo) -> Result<Self> {
+        require!(info.data_len() != RateData::SPACE, ErrorCode::AlreadyMigrated);
+
+        let data = info.try_borrow_data()?;
+        require!(
+            data.len() >= 8 && &data[..8] == RateData::DISCRIMINATOR,
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+        );
+        // Legacy accounts may have trailing space, so don't require an exact fit.
+        Self::deserialize(&mut &data[8..])
+            .map_err(|_| error!(anchor_lang::error::ErrorCode::AccountDidNotDeserialize))
+    }
+
+    // Copies every field into a freshly zeroed zero-copy account.
+    pub fn migrate_into(self, rate_data: &mut RateData) -> Result<()> {
+        require!(self.oracles.len() <= MAX_ORACLES, ErrorCode::TooManyOracles);
+
+        rate_data.authority = self.authority;
+        rate_data.base = fixed_str(&self.base);
+        rate_data.quote = fixed_str(&self.quote);
+        rate_data.bump = self.bump;
+        rate_data.exponent = self.exponent;
+        rate_data.aggregate_rate = self.aggregate_rate;
+        rate_data.num_contributors = self.num_contributors;
+        rate_data.aggregate_timestamp = self.aggregate_timestamp;
+        rate_data.aggregation_window_secs = self.aggregation_window_secs;
+        rate_data.max_staleness_secs = self.max_staleness_secs;
+        rate_data.price_cumulative = self.price_cumulative;
+        rate_data.live_secs_cumulative = self.live_secs_cumulative;
+        rate_data.cumulative_timestamp = self.cumulative_timestamp;
+
+        for oracle in self.oracles {
+            rate_data.push_oracle(Oracle {
+                name: fixed_str(&oracle.name),
+                pubkey: oracle.pubkey,
+                rate: oracle.rate,
+                last_updated: oracle.last_updated,
+                weight: DEFAULT_ORACLE_WEIGHT,
+                ..Zeroable::zeroed()
+            })?;
+        }
+        Ok(())
+    }
+}
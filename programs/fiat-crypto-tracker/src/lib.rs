@@ -1,61 +1,1101 @@
+// `#[program]` expands to IDL account handlers that still call the deprecated
+// `AccountInfo::realloc`.
+#![allow(deprecated)]
+
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use bytemuck::Zeroable;
+
+pub mod admin;
+pub mod aggregation;
+pub mod checked;
+pub mod commit_policy;
+#[cfg(feature = "delegation")]
+pub mod delegation;
+pub mod fixed_point;
+pub mod legacy;
+#[cfg(feature = "cpi")]
+pub mod reader;
+pub mod reputation;
+pub mod rewards;
+pub mod staking;
+pub mod twap;
+
+use admin::AdminAction;
+use aggregation::AggregationMethod;
+#[cfg(feature = "delegation")]
+use delegation::{
+    DELEGATE_BUFFER_SEED, DELEGATION_METADATA_SEED, DELEGATION_PROGRAM_ID, DELEGATION_RECORD_SEED,
+    MAGIC_CONTEXT_ID, MAGIC_PROGRAM_ID, UNDELEGATE_BUFFER_SEED,
+};
+use fixed_point::{change_bps, deviation_bps, format_scaled, MAX_EXPONENT};
+use reputation::OracleReputation;
+use staking::OracleStake;
 
 declare_id!("2Q4J9MoBr6eM8jBBzPcDbSTfG7rKLsm68mYDDLfDZ5kE");
 
+pub const RATE_DATA_SEED: &[u8] = b"rate_data";
+pub const RATE_HISTORY_SEED: &[u8] = b"rate_history";
+pub const ADMIN_PROPOSAL_SEED: &[u8] = b"admin_proposal";
+pub const ORACLE_STAKE_SEED: &[u8] = b"oracle_stake";
+pub const STAKE_VAULT_SEED: &[u8] = b"stake_vault";
+pub const REWARD_VAULT_SEED: &[u8] = b"reward_vault";
+// Sits between the base and quote codes in the PDA seeds so that e.g.
+// USDT/NGN and USD/TNGN can never derive the same address.
+pub const PAIR_SEPARATOR: &[u8] = b"/";
+// Longest currency code accepted, e.g. "USDT".
+pub const MAX_CURRENCY_CODE_LEN: usize = 8;
+// Longest oracle name accepted, e.g. "ExchangeRate-API".
+pub const MAX_ORACLE_NAME_LEN: usize = 32;
+// Most oracles a single feed can have.
+pub const MAX_ORACLES: usize = 16;
+// Weight a new oracle gets in the weighted aggregation methods.
+pub const DEFAULT_ORACLE_WEIGHT: u16 = 1;
+// Most members a feed's admin council can have.
+pub const MAX_COUNCIL_MEMBERS: usize = 7;
+// Oracles that have not updated within this many seconds are left out of the
+// aggregate until the authority configures a different window.
+pub const DEFAULT_AGGREGATION_WINDOW_SECS: i64 = 300;
+// `get_rate` refuses to return an aggregate older than this many seconds until
+// the authority configures a different limit.
+pub const DEFAULT_MAX_STALENESS_SECS: i64 = 60;
+// Number of updates a new feed's history keeps before overwriting the oldest.
+pub const DEFAULT_HISTORY_CAPACITY: u32 = 128;
+// Keeps the history account within the 10KiB an account can grow by per instruction.
+pub const MAX_HISTORY_CAPACITY: u32 = 144;
+// Keeps a `get_history` result within the 1KiB return data limit.
+pub const MAX_HISTORY_READ: u32 = 15;
+// Aggregate changes made outside `update_rate` that a feed remembers for `get_twap`.
+pub const MAX_CHECKPOINTS: usize = 16;
+
 #[program]
 pub mod exchange_rate_tracker {
     use super::*;
 
-    // Initializes the main data account that will store the exchange rates.
-    // This only needs to be called once when deploying the program.
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        let rate_data = &mut ctx.accounts.rate_data;
+    // Initializes the data account that will store the exchange rates for one
+    // currency pair. This needs to be called once per pair (e.g. USD/NGN, GHS/NGN).
+    // Rates for the pair are submitted scaled by 10^exponent, which cannot change later.
+    pub fn initialize(ctx: Context<Initialize>, pair: CurrencyPair, exponent: u8) -> Result<()> {
+        pair.validate()?;
+        require!(exponent <= MAX_EXPONENT, ErrorCode::InvalidExponent);
+
+        let mut rate_data = ctx.accounts.rate_data.load_init()?;
         rate_data.authority = *ctx.accounts.authority.key;
-        rate_data.oracles = Vec::new();
-        msg!("Exchange rate tracker initialized!");
+        rate_data.base = fixed_str(&pair.base);
+        rate_data.quote = fixed_str(&pair.quote);
+        rate_data.bump = ctx.bumps.rate_data;
+        rate_data.exponent = exponent;
+        rate_data.aggregation_window_secs = DEFAULT_AGGREGATION_WINDOW_SECS;
+        rate_data.max_staleness_secs = DEFAULT_MAX_STALENESS_SECS;
+        rate_data.cumulative_timestamp = Clock::get()?.unix_timestamp;
+
+        let rate_history = &mut ctx.accounts.rate_history;
+        rate_history.bump = ctx.bumps.rate_history;
+        rate_history.capacity = DEFAULT_HISTORY_CAPACITY;
+        rate_history.entries = Vec::new();
+        msg!("Exchange rate tracker initialized for {}/{}!", pair.base, pair.quote);
+        emit!(TrackerInitialized {
+            rate_data: ctx.accounts.rate_data.key(),
+            authority: ctx.accounts.authority.key(),
+            base: pair.base,
+            quote: pair.quote,
+            exponent,
+        });
         Ok(())
     }
 
     // Adds a new oracle (data source) to the tracker.
     // Only the program's authority can add new oracles.
     // Oracles are identified by a name (e.g., "Parallel Market") and their public key.
-    pub fn add_oracle(ctx: Context<ManageOracle>, name: String, oracle_pubkey: Pubkey) -> Result<()> {
-        let rate_data = &mut ctx.accounts.rate_data;
+    pub fn add_oracle(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        name: String,
+        oracle_pubkey: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::AddOracle { name, pubkey: oracle_pubkey })
+    }
 
-        // Check if an oracle with the same public key already exists to prevent duplicates.
-        if rate_data.oracles.iter().any(|o| o.pubkey == oracle_pubkey) {
-            return err!(ErrorCode::OracleAlreadyExists);
-        }
+    // Removes an oracle from the tracker, e.g. a retired or compromised feed.
+    // Only the program's authority can remove oracles. Rewards the oracle has not
+    // claimed are forfeited, see `rewards`.
+    pub fn remove_oracle(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        oracle_pubkey: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::RemoveOracle { pubkey: oracle_pubkey })
+    }
+
+    // Swaps the key an oracle signs with, e.g. after a key leak.
+    // The oracle keeps its name, last rate and timestamp.
+    pub fn replace_oracle_key(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        old_pubkey: Pubkey,
+        new_pubkey: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::ReplaceOracleKey { old_pubkey, new_pubkey })
+    }
+
+    // Sets how recent an oracle's rate must be to count towards the aggregate.
+    // Only the program's authority can change the window.
+    pub fn set_aggregation_window(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        window_secs: i64,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetAggregationWindow { window_secs })
+    }
+
+    // Sets how old the aggregate may be before `get_rate` rejects it.
+    // Only the program's authority can change the limit.
+    pub fn set_max_staleness(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        max_staleness_secs: i64,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetMaxStaleness { max_staleness_secs })
+    }
+
+    // Sets how far, in basis points, an update may move from the current aggregate
+    // (or the oracle's own previous rate while there is no fresh aggregate, see
+    // `RateData::exceeds_deviation`). 0 disables the check.
+    pub fn set_max_deviation(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        max_deviation_bps: u16,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetMaxDeviation { max_deviation_bps })
+    }
+
+    // Sets how long every oracle must wait between its own updates, so a
+    // misbehaving publisher cannot hog the feed. 0 disables the limit.
+    pub fn set_min_update_interval(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        min_update_interval_secs: i64,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetMinUpdateInterval { min_update_interval_secs })
+    }
 
-        // Create and add the new oracle to the list.
-        let new_oracle = Oracle {
-            name,
+    // Overrides the feed's minimum update interval for one oracle, or clears the
+    // override with `None`.
+    pub fn set_oracle_min_update_interval(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        oracle_pubkey: Pubkey,
+        min_update_interval_secs: Option<i64>,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetOracleMinUpdateInterval {
             pubkey: oracle_pubkey,
-            rate: 0, // Initialize rate to 0
-            last_updated: 0, // Initialize last updated timestamp to 0
-        };
-        rate_data.oracles.push(new_oracle);
-        msg!("Oracle {} with pubkey {} added.", rate_data.oracles.last().unwrap().name, oracle_pubkey);
-        Ok(())
+            min_update_interval_secs,
+        })
+    }
+
+    // Sets how much an oracle counts towards the aggregate under the weighted
+    // aggregation methods. A weight of 0 leaves it out of them entirely.
+    pub fn set_oracle_weight(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        oracle_pubkey: Pubkey,
+        weight: u16,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetOracleWeight { pubkey: oracle_pubkey, weight })
+    }
+
+    // Sets the statistic the feed's aggregate is computed with.
+    pub fn set_aggregation_method(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        method: AggregationMethod,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetAggregationMethod { method })
+    }
+
+    // Chooses whether updates outside the max deviation fail, or are dropped and
+    // counted against the oracle's reputation while the transaction succeeds.
+    pub fn set_outlier_policy(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        policy: OutlierPolicy,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetOutlierPolicy { policy })
+    }
+
+    // Sets every scored oracle's weight from its reputation, see
+    // `Oracle::reputation_weight`. Oracles without a scored update keep their weight.
+    pub fn apply_reputation_weights(ctx: Context<ManageOracle>, _pair: CurrencyPair) -> Result<()> {
+        ctx.accounts.apply(AdminAction::ApplyReputationWeights)
+    }
+
+    // Sets the mint oracles stake, the token account slashed stake goes to, the
+    // stake an oracle needs to be active and how long unstaked tokens stay
+    // slashable. The mint can only change while nothing is staked.
+    pub fn configure_staking(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        stake_mint: Pubkey,
+        treasury: Pubkey,
+        min_stake: u64,
+        unbonding_period_secs: i64,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::ConfigureStaking {
+            stake_mint,
+            treasury,
+            min_stake,
+            unbonding_period_secs,
+        })
+    }
+
+    // Approves slashing up to `amount` of an oracle's stake, e.g. after a dispute
+    // over a bad rate. Anyone can then carry it out with `slash`. The key may
+    // since have been rotated out or removed.
+    pub fn approve_slash(
+        ctx: Context<ApproveSlash>,
+        _pair: CurrencyPair,
+        oracle_pubkey: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        let rate_data_key = ctx.accounts.rate_data.key();
+        let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+        AdminAction::ApproveSlash { pubkey: oracle_pubkey, amount }.apply(
+            &mut rate_data,
+            rate_data_key,
+            Clock::get()?.unix_timestamp,
+            Some(&mut ctx.accounts.oracle_stake),
+        )
+    }
+
+    // Sets the mint oracles are rewarded in (the native mint for SOL), the reward
+    // for each accepted update and the most one oracle can earn per epoch. The
+    // mint cannot change once set, since the reward vault is created for it.
+    pub fn configure_rewards(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        reward_mint: Pubkey,
+        reward_per_update: u64,
+        max_rewards_per_epoch: u64,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::ConfigureRewards {
+            reward_mint,
+            reward_per_update,
+            max_rewards_per_epoch,
+        })
+    }
+
+    // Sets when `update_rate` on a rollup commits the feed back to the base layer:
+    // after `interval_secs`, after `every_updates` accepted updates or once the
+    // aggregate moves `deviation_bps`, see `commit_policy`. 0 turns a trigger off.
+    pub fn set_commit_policy(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        interval_secs: i64,
+        every_updates: u32,
+        deviation_bps: u16,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetCommitPolicy {
+            interval_secs,
+            every_updates,
+            deviation_bps,
+        })
     }
 
     // Allows a registered oracle to update the exchange rate.
-    // The transaction must be signed by the oracle's key.
-    pub fn update_rate(ctx: Context<UpdateRate>, new_rate: u64) -> Result<()> {
-        let rate_data = &mut ctx.accounts.rate_data;
+    // The transaction must be signed by the oracle's key and `new_rate` is scaled
+    // by the feed's exponent. On a rollup, passing the magic context and program
+    // as remaining accounts lets the update commit the feed when its commit policy
+    // calls for it. The oracle pays for the commit, so it must then be writable.
+    pub fn update_rate<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpdateRate<'info>>,
+        pair: CurrencyPair,
+        new_rate: u64,
+    ) -> Result<()> {
+        let mut rate_data = ctx.accounts.rate_data.load_mut()?;
         let oracle_signer = &ctx.accounts.oracle;
         let clock = Clock::get()?;
+        let exponent = rate_data.exponent;
+
+        // Credit the outgoing aggregate for the time it was in effect.
+        rate_data.accumulate(clock.unix_timestamp);
+        let price_cumulative = rate_data.price_cumulative;
+        let live_secs_cumulative = rate_data.live_secs_cumulative;
 
         // Find the oracle in the list that matches the signer's public key.
-        if let Some(oracle) = rate_data.oracles.iter_mut().find(|o| o.pubkey == *oracle_signer.key) {
+        let old_rate;
+        if let Some(index) = rate_data.find_oracle(oracle_signer.key) {
+            let oracle = &rate_data.oracles[index];
+            require!(!oracle.is_halted(), ErrorCode::OracleHalted);
+            require!(rate_data.meets_min_stake(oracle.stake), ErrorCode::InsufficientStake);
+            require!(
+                clock.unix_timestamp.saturating_sub(oracle.last_updated)
+                    >= rate_data.min_update_interval(oracle),
+                ErrorCode::UpdateTooFrequent
+            );
+            if rate_data.exceeds_deviation(index, new_rate, clock.unix_timestamp) {
+                require!(
+                    rate_data.outlier_policy == OutlierPolicy::Record as u8,
+                    ErrorCode::RateDeviationTooLarge
+                );
+                let oracle = &mut rate_data.oracles[index];
+                oracle.rejected_count += 1;
+                msg!("Rate from {} dropped as an outlier.", oracle.name());
+                emit!(RateRejected {
+                    rate_data: ctx.accounts.rate_data.key(),
+                    oracle: oracle_signer.key(),
+                    rate: new_rate,
+                    aggregate_rate: rate_data.aggregate_rate,
+                    timestamp: clock.unix_timestamp,
+                });
+                return Ok(());
+            }
+
+            let consensus = rate_data.aggregate_rate;
+            let reward = rate_data.reward_per_update;
+            let max_rewards = rate_data.max_rewards_per_epoch;
+            let oracle = &mut rate_data.oracles[index];
+            oracle.record_update(new_rate, consensus);
+            oracle.accrue_reward(reward, max_rewards, clock.epoch);
+            ctx.accounts.rate_history.append(HistoryEntry {
+                timestamp: clock.unix_timestamp,
+                oracle: oracle_signer.key(),
+                rate: new_rate,
+                price_cumulative,
+                live_secs_cumulative,
+            });
+            old_rate = oracle.rate;
             oracle.rate = new_rate;
             oracle.last_updated = clock.unix_timestamp;
-            msg!("Rate updated by {}: 1 USD = {} NGN", oracle.name, new_rate);
+            msg!(
+                "Rate updated by {}: 1 {} = {} {}",
+                oracle.name(),
+                pair.base,
+                format_scaled(new_rate, exponent),
+                pair.quote
+            );
+            rate_data.updates_since_commit = rate_data.updates_since_commit.saturating_add(1);
         } else {
             // If the signer is not a registered oracle, return an error.
             return err!(ErrorCode::UnauthorizedOracle);
         }
 
+        rate_data.recompute_aggregate(clock.unix_timestamp);
+        msg!(
+            "Aggregate rate: {} from {} oracle(s)",
+            format_scaled(rate_data.aggregate_rate, exponent),
+            rate_data.num_contributors
+        );
+        emit!(RateUpdated {
+            rate_data: ctx.accounts.rate_data.key(),
+            oracle: oracle_signer.key(),
+            old_rate,
+            new_rate,
+            change_bps: change_bps(old_rate, new_rate),
+            aggregate_rate: rate_data.aggregate_rate,
+            num_contributors: rate_data.num_contributors,
+            timestamp: clock.unix_timestamp,
+        });
+
+        #[cfg(feature = "delegation")]
+        if let [magic_context, magic_program] = ctx.remaining_accounts {
+            require_keys_eq!(
+                magic_context.key(),
+                MAGIC_CONTEXT_ID,
+                anchor_lang::error::ErrorCode::ConstraintAddress
+            );
+            require_keys_eq!(
+                magic_program.key(),
+                MAGIC_PROGRAM_ID,
+                anchor_lang::error::ErrorCode::ConstraintAddress
+            );
+            if rate_data.commit_due(clock.unix_timestamp) {
+                // The commit needs the feed's accounts unborrowed.
+                drop(rate_data);
+                delegation::commit_feed(
+                    &ctx.accounts.rate_data,
+                    &ctx.accounts.rate_history,
+                    oracle_signer,
+                    magic_context,
+                    magic_program,
+                    false,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the current aggregate rate as return data, so other programs can
+    // read it via CPI. Fails if no oracle contributed to the aggregate or it is
+    // older than the feed's max staleness.
+    pub fn get_rate(ctx: Context<ReadRate>, _pair: CurrencyPair) -> Result<RateQuote> {
+        let rate_data = ctx.accounts.rate_data.load()?;
+        let clock = Clock::get()?;
+
+        let age = clock.unix_timestamp.saturating_sub(rate_data.aggregate_timestamp);
+        require!(
+            rate_data.num_contributors > 0 && age <= rate_data.max_staleness_secs,
+            ErrorCode::StaleRate
+        );
+
+        Ok(RateQuote {
+            rate: rate_data.aggregate_rate,
+            exponent: rate_data.exponent,
+            timestamp: rate_data.aggregate_timestamp,
+        })
+    }
+
+    // Returns the aggregate with how it was formed. Unlike `get_rate` this does
+    // not fail when the aggregate is missing or stale, so callers can apply their
+    // own rules.
+    pub fn get_aggregate(ctx: Context<ReadRate>, _pair: CurrencyPair) -> Result<AggregateQuote> {
+        let rate_data = ctx.accounts.rate_data.load()?;
+        Ok(AggregateQuote {
+            rate: rate_data.aggregate_rate,
+            exponent: rate_data.exponent,
+            timestamp: rate_data.aggregate_timestamp,
+            num_contributors: rate_data.num_contributors,
+            num_oracles: rate_data.num_oracles,
+            method: rate_data.aggregation_method(),
+            max_staleness_secs: rate_data.max_staleness_secs,
+            last_commit_timestamp: rate_data.last_commit_timestamp,
+        })
+    }
+
+    // Returns every oracle's latest rate and whether it counts towards the
+    // aggregate right now.
+    pub fn get_all_rates(ctx: Context<ReadRate>, _pair: CurrencyPair) -> Result<Vec<OracleRate>> {
+        let rate_data = ctx.accounts.rate_data.load()?;
+        let now = Clock::get()?.unix_timestamp;
+        Ok(rate_data
+            .active_oracles()
+            .iter()
+            .map(|oracle| OracleRate {
+                pubkey: oracle.pubkey,
+                rate: oracle.rate,
+                last_updated: oracle.last_updated,
+                weight: oracle.weight,
+                contributing: rate_data.contributes(oracle, now),
+            })
+            .collect())
+    }
+
+    // Returns every oracle's update count, rejections and mean distance from
+    // consensus, closest to consensus first.
+    pub fn get_reputation(
+        ctx: Context<ReadRate>,
+        _pair: CurrencyPair,
+    ) -> Result<Vec<OracleReputation>> {
+        Ok(ctx.accounts.rate_data.load()?.reputation_table())
+    }
+
+    // Returns the time-weighted average of the aggregate over the last `window_secs`
+    // seconds, ignoring any time with no aggregate. The window must be covered by
+    // the retained history and admin checkpoints, see `twap`, and the aggregate
+    // must not be stale.
+    pub fn get_twap(
+        ctx: Context<ReadHistory>,
+        _pair: CurrencyPair,
+        window_secs: i64,
+    ) -> Result<TwapQuote> {
+        let rate_data = ctx.accounts.rate_data.load()?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(window_secs > 0, ErrorCode::InvalidTwapWindow);
+
+        let age = now.saturating_sub(rate_data.aggregate_timestamp);
+        require!(
+            rate_data.num_contributors > 0 && age <= rate_data.max_staleness_secs,
+            ErrorCode::StaleRate
+        );
+
+        let start = rate_data.observe_at(&ctx.accounts.rate_history, now - window_secs)?;
+        let end = rate_data.observe(now);
+        let twap = twap::twap(start, end).ok_or(ErrorCode::StaleRate)?;
+
+        Ok(TwapQuote {
+            twap,
+            exponent: rate_data.exponent,
+            window_secs,
+            timestamp: now,
+        })
+    }
+
+    // Returns up to `limit` history entries starting at sequence number `from_seq`,
+    // oldest first. Entries that have already been overwritten are skipped, so the
+    // result starts at the oldest retained entry if `from_seq` is too old.
+    pub fn get_history(
+        ctx: Context<ReadHistory>,
+        _pair: CurrencyPair,
+        from_seq: u64,
+        limit: u32,
+    ) -> Result<HistoryRange> {
+        require!(limit <= MAX_HISTORY_READ, ErrorCode::HistoryReadTooLarge);
+        Ok(ctx.accounts.rate_history.range(from_seq, limit))
+    }
+
+    // Changes how many entries the feed's history keeps, keeping the most recent
+    // ones when shrinking. Only the program's authority can resize the history,
+    // and it pays for (or is refunded) the rent difference. A council can't pay,
+    // so feeds with one must dissolve it before resizing.
+    pub fn resize_history(
+        ctx: Context<ResizeHistory>,
+        _pair: CurrencyPair,
+        new_capacity: u32,
+    ) -> Result<()> {
+        // The account itself was already resized by the `realloc` constraint.
+        ctx.accounts.rate_history.set_capacity(new_capacity);
+        msg!("History capacity set to {} entries.", new_capacity);
+        emit!(HistoryResized { rate_data: ctx.accounts.rate_data.key(), capacity: new_capacity });
+        Ok(())
+    }
+
+    // Converts a feed created before `RateData` became zero-copy to the new layout,
+    // keeping its oracles, aggregate and settings. Only the feed's authority can
+    // migrate it, and it pays for (or is refunded) the rent difference.
+    pub fn migrate_rate_data(ctx: Context<MigrateRateData>, _pair: CurrencyPair) -> Result<()> {
+        let info = ctx.accounts.rate_data.to_account_info();
+        let authority = ctx.accounts.authority.to_account_info();
+
+        let legacy = legacy::LegacyRateData::try_from_account(&info)?;
+        require_keys_eq!(
+            legacy.authority,
+            authority.key(),
+            anchor_lang::error::ErrorCode::ConstraintHasOne
+        );
+
+        // Top up or refund rent so the account stays exactly rent-exempt.
+        let rent_exempt = Rent::get()?.minimum_balance(RateData::SPACE);
+        let balance = info.lamports();
+        if balance < rent_exempt {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer { from: authority.clone(), to: info.clone() },
+                ),
+                rent_exempt - balance,
+            )?;
+        } else if balance > rent_exempt {
+            **info.try_borrow_mut_lamports()? = rent_exempt;
+            **authority.try_borrow_mut_lamports()? += balance - rent_exempt;
+        }
+
+        // Both layouts share a discriminator, so only the body needs rewriting.
+        info.resize(RateData::SPACE)?;
+        let mut data = info.try_borrow_mut_data()?;
+        data[8..].fill(0);
+        legacy.migrate_into(bytemuck::from_bytes_mut(&mut data[8..]))?;
+        msg!("Rate data migrated to the zero-copy layout.");
+        emit!(RateDataMigrated { rate_data: info.key(), authority: authority.key() });
+        Ok(())
+    }
+
+    // First step of handing a feed to a new authority, e.g. when rotating keys.
+    // Nothing changes until the proposed key accepts; proposing again replaces
+    // any earlier proposal.
+    pub fn propose_authority(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::ProposeAuthority { new_authority })
+    }
+
+    // Second step: the proposed key signs to become the feed's authority.
+    pub fn accept_authority(ctx: Context<AcceptAuthority>, _pair: CurrencyPair) -> Result<()> {
+        let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+        let old_authority = rate_data.authority;
+        rate_data.authority = rate_data.pending_authority;
+        rate_data.pending_authority = Pubkey::default();
+        emit!(AuthorityTransferAccepted {
+            rate_data: ctx.accounts.rate_data.key(),
+            old_authority,
+            new_authority: rate_data.authority,
+        });
+        Ok(())
+    }
+
+    // Withdraws a pending authority proposal. Only the current authority can cancel.
+    pub fn cancel_authority_transfer(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::CancelAuthorityTransfer)
+    }
+
+    // Halts `update_rate` and every read of the feed, e.g. during an incident.
+    // Only the program's authority can pause or unpause a feed.
+    pub fn pause(ctx: Context<ManageOracle>, _pair: CurrencyPair) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetStatus { status: FeedStatus::Paused })
+    }
+
+    pub fn unpause(ctx: Context<ManageOracle>, _pair: CurrencyPair) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetStatus { status: FeedStatus::Active })
+    }
+
+    // Stops a single oracle from updating and drops its rate from the aggregate,
+    // e.g. after its key leaked or its source started returning garbage.
+    pub fn set_oracle_halted(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        oracle_pubkey: Pubkey,
+        halted: bool,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetOracleHalted { pubkey: oracle_pubkey, halted })
+    }
+
+    // Moves `amount` of the signer's tokens into their stake vault for the feed,
+    // creating the vault on first use. Any key can stake, registered or not.
+    pub fn stake(ctx: Context<Stake>, _pair: CurrencyPair, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidStakeAmount);
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.staker_tokens.to_account_info(),
+                    mint: ctx.accounts.stake_mint.to_account_info(),
+                    to: ctx.accounts.stake_vault.to_account_info(),
+                    authority: ctx.accounts.staker.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.stake_mint.decimals,
+        )?;
+
+        let staker = ctx.accounts.staker.key();
+        let oracle_stake = &mut ctx.accounts.oracle_stake;
+        oracle_stake.rate_data = ctx.accounts.rate_data.key();
+        oracle_stake.staker = staker;
+        oracle_stake.bump = ctx.bumps.oracle_stake;
+        oracle_stake.vault_bump = ctx.bumps.stake_vault;
+        oracle_stake.amount = oracle_stake
+            .amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+        rate_data.total_staked = rate_data
+            .total_staked
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        rate_data.sync_stake(&staker, oracle_stake.amount, Clock::get()?.unix_timestamp);
+        emit!(StakeDeposited {
+            rate_data: oracle_stake.rate_data,
+            staker,
+            amount,
+            bonded: oracle_stake.amount,
+        });
+        Ok(())
+    }
+
+    // Copies a key's bonded stake onto its oracle entry. Oracles are added, and
+    // keys rotated in, without stake, so one that staked beforehand (or was
+    // removed and added back) needs this before it can update. Anyone can call it.
+    pub fn sync_stake(ctx: Context<SyncStake>, _pair: CurrencyPair) -> Result<()> {
+        let oracle_stake = &ctx.accounts.oracle_stake;
+        let now = Clock::get()?.unix_timestamp;
+        let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+        require!(
+            rate_data.find_oracle(&oracle_stake.staker).is_some(),
+            ErrorCode::OracleNotFound
+        );
+        rate_data.sync_stake(&oracle_stake.staker, oracle_stake.amount, now);
+        emit!(StakeSynced {
+            rate_data: oracle_stake.rate_data,
+            staker: oracle_stake.staker,
+            bonded: oracle_stake.amount,
+        });
+        Ok(())
+    }
+
+    // Starts unbonding `amount` of the signer's stake. It stops counting towards
+    // the minimum straight away and can be withdrawn after the unbonding period.
+    // Requesting more restarts the period for everything that is unbonding.
+    pub fn request_unstake(
+        ctx: Context<RequestUnstake>,
+        _pair: CurrencyPair,
+        amount: u64,
+    ) -> Result<()> {
+        let oracle_stake = &mut ctx.accounts.oracle_stake;
+        require!(
+            amount > 0 && amount <= oracle_stake.amount,
+            ErrorCode::InsufficientStake
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+        oracle_stake.amount -= amount;
+        oracle_stake.unbonding_amount += amount;
+        oracle_stake.unbonding_until = now.saturating_add(rate_data.unbonding_period_secs);
+        rate_data.sync_stake(&oracle_stake.staker, oracle_stake.amount, now);
+        emit!(UnstakeRequested {
+            rate_data: oracle_stake.rate_data,
+            staker: oracle_stake.staker,
+            amount,
+            unbonding_until: oracle_stake.unbonding_until,
+        });
+        Ok(())
+    }
+
+    // Returns the signer's unbonded stake once the unbonding period has passed.
+    pub fn withdraw_stake(ctx: Context<WithdrawStake>, pair: CurrencyPair) -> Result<()> {
+        let oracle_stake = &mut ctx.accounts.oracle_stake;
+        let amount = oracle_stake.unbonding_amount;
+        require!(amount > 0, ErrorCode::NothingToWithdraw);
+        require!(
+            Clock::get()?.unix_timestamp >= oracle_stake.unbonding_until,
+            ErrorCode::StakeStillUnbonding
+        );
+        oracle_stake.unbonding_amount = 0;
+
+        let bump = {
+            let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+            rate_data.total_staked -= amount;
+            rate_data.bump
+        };
+        staking::transfer_from_vault(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.stake_vault.to_account_info(),
+            ctx.accounts.stake_mint.to_account_info(),
+            ctx.accounts.staker_tokens.to_account_info(),
+            ctx.accounts.rate_data.to_account_info(),
+            &pair,
+            bump,
+            amount,
+            ctx.accounts.stake_mint.decimals,
+        )?;
+        emit!(StakeWithdrawn {
+            rate_data: oracle_stake.rate_data,
+            staker: oracle_stake.staker,
+            amount,
+        });
+        Ok(())
+    }
+
+    // Moves the slash approved against a key from its stake to the feed's
+    // treasury, taking bonded stake first and then stake that is unbonding.
+    // Anyone can call this once `approve_slash` has run, whether or not the key
+    // is still an oracle.
+    pub fn slash(ctx: Context<Slash>, pair: CurrencyPair, oracle_pubkey: Pubkey) -> Result<()> {
+        let (amount, bump) = {
+            let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+            let oracle_stake = &mut ctx.accounts.oracle_stake;
+            let approved = oracle_stake.slash_approved;
+            require!(approved > 0, ErrorCode::NoSlashApproved);
+            oracle_stake.slash_approved = 0;
+
+            let amount = oracle_stake.slash(approved);
+            rate_data.total_staked -= amount;
+            rate_data.sync_stake(&oracle_pubkey, oracle_stake.amount, Clock::get()?.unix_timestamp);
+            (amount, rate_data.bump)
+        };
+
+        if amount > 0 {
+            staking::transfer_from_vault(
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.stake_vault.to_account_info(),
+                ctx.accounts.stake_mint.to_account_info(),
+                ctx.accounts.treasury.to_account_info(),
+                ctx.accounts.rate_data.to_account_info(),
+                &pair,
+                bump,
+                amount,
+                ctx.accounts.stake_mint.decimals,
+            )?;
+        }
+        msg!("Slashed {} from oracle {}.", amount, oracle_pubkey);
+        emit!(OracleSlashed {
+            rate_data: ctx.accounts.rate_data.key(),
+            oracle: oracle_pubkey,
+            amount,
+        });
+        Ok(())
+    }
+
+    // Adds `amount` of the funder's tokens to the feed's reward vault, creating
+    // the vault on first use. Anyone can fund a feed's rewards.
+    pub fn fund_rewards(ctx: Context<FundRewards>, _pair: CurrencyPair, amount: u64) -> Result<()> {
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.funder_tokens.to_account_info(),
+                    mint: ctx.accounts.reward_mint.to_account_info(),
+                    to: ctx.accounts.reward_vault.to_account_info(),
+                    authority: ctx.accounts.funder.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.reward_mint.decimals,
+        )?;
+        emit!(RewardsFunded {
+            rate_data: ctx.accounts.rate_data.key(),
+            funder: ctx.accounts.funder.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    // Pays the signing oracle the rewards it has accrued, or as much of them as
+    // the vault holds; the rest stays owed.
+    pub fn claim_rewards(ctx: Context<ClaimRewards>, pair: CurrencyPair) -> Result<()> {
+        let oracle_key = ctx.accounts.oracle.key();
+        let (amount, bump) = {
+            let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+            let index = rate_data
+                .find_oracle(&oracle_key)
+                .ok_or(ErrorCode::UnauthorizedOracle)?;
+            let oracle = &mut rate_data.oracles[index];
+            let amount = oracle.accrued_rewards.min(ctx.accounts.reward_vault.amount);
+            require!(amount > 0, ErrorCode::NothingToClaim);
+            oracle.accrued_rewards -= amount;
+            (amount, rate_data.bump)
+        };
+
+        staking::transfer_from_vault(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.reward_vault.to_account_info(),
+            ctx.accounts.reward_mint.to_account_info(),
+            ctx.accounts.oracle_tokens.to_account_info(),
+            ctx.accounts.rate_data.to_account_info(),
+            &pair,
+            bump,
+            amount,
+            ctx.accounts.reward_mint.decimals,
+        )?;
+        emit!(RewardsClaimed {
+            rate_data: ctx.accounts.rate_data.key(),
+            oracle: oracle_key,
+            amount,
+        });
+        Ok(())
+    }
+
+    // Hands admin changes to a council of up to 7 keys, `threshold` of which must
+    // approve each change. Once set, the council can only be changed or dissolved
+    // by a council proposal.
+    pub fn set_council(
+        ctx: Context<ManageOracle>,
+        _pair: CurrencyPair,
+        members: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        ctx.accounts.apply(AdminAction::SetCouncil { members, threshold })
+    }
+
+    // Proposes an admin change to a feed with a council. `proposal_id` must be
+    // the feed's `next_proposal_id`; the proposer's approval is counted straight away.
+    pub fn propose_admin_action(
+        ctx: Context<ProposeAdminAction>,
+        _pair: CurrencyPair,
+        proposal_id: u64,
+        action: AdminAction,
+    ) -> Result<()> {
+        let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+        require!(proposal_id == rate_data.next_proposal_id, ErrorCode::InvalidProposalId);
+        rate_data.next_proposal_id += 1;
+
+        let proposer = ctx.accounts.proposer.key();
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.rate_data = ctx.accounts.rate_data.key();
+        proposal.id = proposal_id;
+        proposal.proposer = proposer;
+        proposal.bump = ctx.bumps.proposal;
+        proposal.action = action;
+        proposal.approvals = vec![proposer];
+        emit!(AdminActionProposed {
+            rate_data: proposal.rate_data,
+            proposal_id,
+            proposer,
+            action: proposal.action.clone(),
+        });
+        Ok(())
+    }
+
+    // Records a council member's approval of a pending proposal.
+    pub fn approve_admin_action(
+        ctx: Context<ApproveAdminAction>,
+        _pair: CurrencyPair,
+        proposal_id: u64,
+    ) -> Result<()> {
+        let member = ctx.accounts.member.key();
+        let rate_data = ctx.accounts.rate_data.load()?;
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.prune_approvals(&rate_data);
+        require!(!proposal.approvals.contains(&member), ErrorCode::AlreadyApproved);
+        proposal.approvals.push(member);
+
+        emit!(AdminActionApproved {
+            rate_data: proposal.rate_data,
+            proposal_id,
+            member,
+            approvals: proposal.approval_count(&rate_data) as u8,
+            threshold: rate_data.council_threshold,
+        });
+        Ok(())
+    }
+
+    // Applies a proposal once enough current council members have approved it
+    // and closes it, refunding the proposer. Anyone can execute. An `ApproveSlash`
+    // proposal takes the slashed key's `OracleStake` as the first remaining account.
+    pub fn execute_admin_action<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteAdminAction<'info>>,
+        _pair: CurrencyPair,
+        proposal_id: u64,
+    ) -> Result<()> {
+        let rate_data_key = ctx.accounts.rate_data.key();
+        let mut rate_data = ctx.accounts.rate_data.load_mut()?;
+        require!(rate_data.has_council(), ErrorCode::NoCouncil);
+
+        let proposal = &ctx.accounts.proposal;
+        require!(
+            proposal.approval_count(&rate_data) >= rate_data.council_threshold as usize,
+            ErrorCode::NotEnoughApprovals
+        );
+        let mut oracle_stake = match proposal.action {
+            AdminAction::ApproveSlash { .. } => {
+                let info = ctx.remaining_accounts.first().ok_or(ErrorCode::InvalidOracleStake)?;
+                Some(Account::<OracleStake>::try_from(info)?)
+            }
+            _ => None,
+        };
+        let now = Clock::get()?.unix_timestamp;
+        let action = proposal.action.clone();
+        action.apply(&mut rate_data, rate_data_key, now, oracle_stake.as_deref_mut())?;
+        if let Some(oracle_stake) = oracle_stake {
+            oracle_stake.exit(&crate::ID)?;
+        }
+        emit!(AdminActionExecuted { rate_data: rate_data_key, proposal_id });
+        Ok(())
+    }
+
+    // Withdraws a proposal that has not been executed, refunding its rent.
+    // Only the proposer can cancel.
+    pub fn cancel_admin_action(
+        ctx: Context<CancelAdminAction>,
+        _pair: CurrencyPair,
+        proposal_id: u64,
+    ) -> Result<()> {
+        emit!(AdminActionCancelled { rate_data: ctx.accounts.rate_data.key(), proposal_id });
+        Ok(())
+    }
+
+    // Hands the feed to an ephemeral rollup, see `delegation`. Its history goes
+    // with it since `update_rate` appends to it. The rollup commits both back to
+    // the base layer every `commit_frequency_ms`, and only `validator` may host
+    // them if one is given. Only the feed's authority can delegate it.
+    #[cfg(feature = "delegation")]
+    pub fn delegate_rate_data(
+        ctx: Context<DelegateRateData>,
+        pair: CurrencyPair,
+        commit_frequency_ms: u32,
+        validator: Option<Pubkey>,
+    ) -> Result<()> {
+        let accounts = &ctx.accounts;
+        {
+            let rate_data = RateData::load_verified(&accounts.rate_data)?;
+            require_keys_eq!(
+                rate_data.authority,
+                accounts.authority.key(),
+                anchor_lang::error::ErrorCode::ConstraintHasOne
+            );
+            require!(!rate_data.has_council(), ErrorCode::CouncilApprovalRequired);
+        }
+
+        let rate_data_key = accounts.rate_data.key();
+        let feed = delegation::DelegateAccounts {
+            payer: &accounts.authority,
+            account: &accounts.rate_data,
+            buffer: &accounts.rate_data_buffer,
+            delegation_record: &accounts.rate_data_delegation_record,
+            delegation_metadata: &accounts.rate_data_delegation_metadata,
+            owner_program: &accounts.owner_program,
+            delegation_program: &accounts.delegation_program,
+            system_program: &accounts.system_program,
+        };
+        delegation::delegate_account(
+            &feed,
+            &[RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+            ctx.bumps.rate_data,
+            ctx.bumps.rate_data_buffer,
+            commit_frequency_ms,
+            validator,
+        )?;
+        let history = delegation::DelegateAccounts {
+            account: &accounts.rate_history,
+            buffer: &accounts.rate_history_buffer,
+            delegation_record: &accounts.rate_history_delegation_record,
+            delegation_metadata: &accounts.rate_history_delegation_metadata,
+            ..feed
+        };
+        delegation::delegate_account(
+            &history,
+            &[RATE_HISTORY_SEED, rate_data_key.as_ref()],
+            ctx.bumps.rate_history,
+            ctx.bumps.rate_history_buffer,
+            commit_frequency_ms,
+            validator,
+        )?;
+
+        msg!("{}/{} delegated to an ephemeral rollup.", pair.base, pair.quote);
+        emit!(FeedDelegated { rate_data: rate_data_key, validator, commit_frequency_ms });
+        Ok(())
+    }
+
+    // Run on the rollup: writes the feed's current rate data and history back to
+    // the base layer, so readers there see the latest rates before the next
+    // periodic commit. Anyone can pay for a commit.
+    #[cfg(feature = "delegation")]
+    pub fn commit_rate_data(ctx: Context<CommitRateData>, _pair: CurrencyPair) -> Result<()> {
+        let accounts = &ctx.accounts;
+        delegation::commit_feed(
+            &accounts.rate_data,
+            &accounts.rate_history,
+            &accounts.payer,
+            &accounts.magic_context,
+            &accounts.magic_program,
+            false,
+        )
+    }
+
+    // Run on the rollup: commits the feed one last time and returns it to the base
+    // layer, where the delegation program hands both accounts back through
+    // `process_undelegation`. Only the feed's authority can undelegate it.
+    #[cfg(feature = "delegation")]
+    pub fn undelegate_rate_data(
+        ctx: Context<UndelegateRateData>,
+        _pair: CurrencyPair,
+    ) -> Result<()> {
+        let accounts = &ctx.accounts;
+        delegation::commit_feed(
+            &accounts.rate_data,
+            &accounts.rate_history,
+            &accounts.authority,
+            &accounts.magic_context,
+            &accounts.magic_program,
+            true,
+        )
+    }
+
+    // Called by the delegation program to give back an account this program
+    // delegated, with the seeds of its address. Re-creates the account with the
+    // data the rollup last committed.
+    #[cfg(feature = "delegation")]
+    pub fn process_undelegation(
+        ctx: Context<ProcessUndelegation>,
+        account_seeds: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        let accounts = &ctx.accounts;
+        delegation::undelegate_account(
+            &accounts.delegated_account,
+            &accounts.buffer,
+            &accounts.payer,
+            &accounts.system_program,
+            &account_seeds,
+        )?;
+        if let Ok(rate_data) = RateData::load_verified(&accounts.delegated_account) {
+            msg!("{}/{} returned from the ephemeral rollup.", rate_data.base(), rate_data.quote());
+            emit!(FeedUndelegated { rate_data: accounts.delegated_account.key() });
+        }
         Ok(())
     }
 }
@@ -64,65 +1104,1503 @@ pub mod exchange_rate_tracker {
 
 // Context for the `initialize` instruction.
 #[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
 pub struct Initialize<'info> {
-    // The account is now a PDA, initialized with seeds and a bump.
+    // The account is a PDA seeded by the currency pair it tracks.
+    #[account(
+        init,
+        payer = authority,
+        space = RateData::SPACE,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    // The pair's price history, created alongside it.
     #[account(
         init,
         payer = authority,
-        space = 8 + 32 + 1024,
-        seeds = [b"rate_data"],
+        space = RateHistory::space(DEFAULT_HISTORY_CAPACITY),
+        seeds = [RATE_HISTORY_SEED, rate_data.key().as_ref()],
         bump
     )]
-    pub rate_data: Account<'info, RateData>,
+    pub rate_history: Account<'info, RateHistory>,
     // The authority who is initializing the program (and will manage oracles).
     #[account(mut)]
     pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
-// Context for adding or removing oracles.
+// Context for adding or removing oracles and changing a feed's settings.
 #[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
 pub struct ManageOracle<'info> {
-    // This now accesses the PDA using the same seeds and bump.
-    #[account(mut, has_one = authority, seeds = [b"rate_data"], bump)]
-    pub rate_data: Account<'info, RateData>,
+    // This accesses the pair's PDA using the same seeds and the stored bump.
+    // Feeds with a council only accept admin changes through proposals.
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = !rate_data.load()?.has_council() @ ErrorCode::CouncilApprovalRequired
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
     // The authority of the program. The signature is checked by `has_one`.
     pub authority: Signer<'info>,
 }
 
+impl ManageOracle<'_> {
+    pub fn apply(&self, action: AdminAction) -> Result<()> {
+        let mut rate_data = self.rate_data.load_mut()?;
+        action.apply(&mut rate_data, self.rate_data.key(), Clock::get()?.unix_timestamp, None)
+    }
+}
+
 // Context for an oracle updating a rate.
 #[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
 pub struct UpdateRate<'info> {
-    // This also accesses the PDA using the same seeds and bump.
-    #[account(mut, seeds = [b"rate_data"], bump)]
-    pub rate_data: Account<'info, RateData>,
+    // This also accesses the pair's PDA using the same seeds and the stored bump.
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = !rate_data.load()?.is_paused() @ ErrorCode::FeedPaused
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    // Every accepted update is appended to the pair's history.
+    #[account(mut, seeds = [RATE_HISTORY_SEED, rate_data.key().as_ref()], bump = rate_history.bump)]
+    pub rate_history: Account<'info, RateHistory>,
     // The oracle updating the rate. Their signature is required.
     pub oracle: Signer<'info>,
 }
 
-// The main account that stores the list of oracles and their data.
-#[account]
-pub struct RateData {
-    pub authority: Pubkey,
-    pub oracles: Vec<Oracle>,
+// Context for reading a pair's rate. Nothing is written and no signature is needed.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct ReadRate<'info> {
+    #[account(
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = !rate_data.load()?.is_paused() @ ErrorCode::FeedPaused
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
 }
 
-// Represents a single data source (e.g., a bank, parallel market).
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct Oracle {
-    pub name: String,       // e.g., "Bank A", "Crypto Exchange"
-    pub pubkey: Pubkey,     // The public key of the oracle allowed to update this rate
-    pub rate: u64,          // The USD/NGN rate (e.g., 1450)
-    pub last_updated: i64,  // Unix timestamp of the last update
+// Context for reading a pair's price history.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct ReadHistory<'info> {
+    #[account(
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = !rate_data.load()?.is_paused() @ ErrorCode::FeedPaused
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(seeds = [RATE_HISTORY_SEED, rate_data.key().as_ref()], bump = rate_history.bump)]
+    pub rate_history: Account<'info, RateHistory>,
 }
 
+// Context for resizing a pair's price history.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair, new_capacity: u32)]
+pub struct ResizeHistory<'info> {
+    #[account(
+        has_one = authority,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = !rate_data.load()?.has_council() @ ErrorCode::CouncilApprovalRequired
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, rate_data.key().as_ref()],
+        bump = rate_history.bump,
+        constraint = new_capacity > 0 && new_capacity <= MAX_HISTORY_CAPACITY
+            @ ErrorCode::InvalidHistoryCapacity,
+        realloc = RateHistory::space(new_capacity),
+        realloc::payer = authority,
+        realloc::zero = false
+    )]
+    pub rate_history: Account<'info, RateHistory>,
+    // The authority of the program. Pays for any extra space.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
 
-// ========== ERRORS ==========
+// Context for the proposed authority accepting a feed.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = rate_data.load()?.pending_authority == new_authority.key()
+            @ ErrorCode::NotPendingAuthority
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    // The key proposed by `propose_authority`. Their signature is required.
+    pub new_authority: Signer<'info>,
+}
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("The provided oracle is not authorized to update rates.")]
-    UnauthorizedOracle,
-    #[msg("An oracle with this public key already exists.")]
-    OracleAlreadyExists,
+// Context for a council member proposing an admin change.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair, proposal_id: u64, action: AdminAction)]
+pub struct ProposeAdminAction<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = rate_data.load()?.is_council_member(proposer.key)
+            @ ErrorCode::NotCouncilMember
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        init,
+        payer = proposer,
+        space = AdminProposal::space(&action),
+        seeds = [ADMIN_PROPOSAL_SEED, rate_data.key().as_ref(), &proposal_id.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, AdminProposal>,
+    // The council member proposing the change. Pays for the proposal account.
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for a council member approving a proposal.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair, proposal_id: u64)]
+pub struct ApproveAdminAction<'info> {
+    #[account(
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = rate_data.load()?.is_council_member(member.key)
+            @ ErrorCode::NotCouncilMember
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        mut,
+        seeds = [ADMIN_PROPOSAL_SEED, rate_data.key().as_ref(), &proposal_id.to_le_bytes()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, AdminProposal>,
+    pub member: Signer<'info>,
+}
+
+// Context for executing an approved proposal.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair, proposal_id: u64)]
+pub struct ExecuteAdminAction<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        mut,
+        has_one = proposer,
+        close = proposer,
+        seeds = [ADMIN_PROPOSAL_SEED, rate_data.key().as_ref(), &proposal_id.to_le_bytes()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, AdminProposal>,
+    /// CHECK: Only receives the proposal's rent; matched against the proposal by `has_one`.
+    #[account(mut)]
+    pub proposer: UncheckedAccount<'info>,
+}
+
+// Context for the proposer withdrawing a proposal.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair, proposal_id: u64)]
+pub struct CancelAdminAction<'info> {
+    #[account(
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        mut,
+        has_one = proposer,
+        close = proposer,
+        seeds = [ADMIN_PROPOSAL_SEED, rate_data.key().as_ref(), &proposal_id.to_le_bytes()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, AdminProposal>,
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+}
+
+// Context for staking into a feed.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct Stake<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = rate_data.load()?.stake_mint != Pubkey::default()
+            @ ErrorCode::StakingNotConfigured
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = OracleStake::SPACE,
+        seeds = [ORACLE_STAKE_SEED, rate_data.key().as_ref(), staker.key().as_ref()],
+        bump
+    )]
+    pub oracle_stake: Account<'info, OracleStake>,
+    // Owned by the rate_data PDA so only the program can move staked tokens.
+    #[account(
+        init_if_needed,
+        payer = staker,
+        seeds = [STAKE_VAULT_SEED, rate_data.key().as_ref(), staker.key().as_ref()],
+        bump,
+        token::mint = stake_mint,
+        token::authority = rate_data,
+        token::token_program = token_program
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = rate_data.load()?.stake_mint)]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+    // The staker's own tokens. The token program checks they own it.
+    #[account(mut, token::mint = stake_mint)]
+    pub staker_tokens: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for copying a key's stake onto its oracle. No signature is needed.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct SyncStake<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        seeds = [ORACLE_STAKE_SEED, rate_data.key().as_ref(), oracle_stake.staker.as_ref()],
+        bump = oracle_stake.bump
+    )]
+    pub oracle_stake: Account<'info, OracleStake>,
+}
+
+// Context for a staker starting to unbond.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct RequestUnstake<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        mut,
+        seeds = [ORACLE_STAKE_SEED, rate_data.key().as_ref(), staker.key().as_ref()],
+        bump = oracle_stake.bump
+    )]
+    pub oracle_stake: Account<'info, OracleStake>,
+    pub staker: Signer<'info>,
+}
+
+// Context for a staker withdrawing unbonded stake.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct WithdrawStake<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        mut,
+        seeds = [ORACLE_STAKE_SEED, rate_data.key().as_ref(), staker.key().as_ref()],
+        bump = oracle_stake.bump
+    )]
+    pub oracle_stake: Account<'info, OracleStake>,
+    #[account(
+        mut,
+        seeds = [STAKE_VAULT_SEED, rate_data.key().as_ref(), staker.key().as_ref()],
+        bump = oracle_stake.vault_bump
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = rate_data.load()?.stake_mint)]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = stake_mint)]
+    pub staker_tokens: InterfaceAccount<'info, TokenAccount>,
+    pub staker: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Context for the authority approving a slash of a key's stake.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair, oracle_pubkey: Pubkey)]
+pub struct ApproveSlash<'info> {
+    // Feeds with a council only accept slashes through proposals.
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = !rate_data.load()?.has_council() @ ErrorCode::CouncilApprovalRequired
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        mut,
+        seeds = [ORACLE_STAKE_SEED, rate_data.key().as_ref(), oracle_pubkey.as_ref()],
+        bump = oracle_stake.bump
+    )]
+    pub oracle_stake: Account<'info, OracleStake>,
+    pub authority: Signer<'info>,
+}
+
+// Context for carrying out an approved slash. No signature is needed.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair, oracle_pubkey: Pubkey)]
+pub struct Slash<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        mut,
+        seeds = [ORACLE_STAKE_SEED, rate_data.key().as_ref(), oracle_pubkey.as_ref()],
+        bump = oracle_stake.bump
+    )]
+    pub oracle_stake: Account<'info, OracleStake>,
+    #[account(
+        mut,
+        seeds = [STAKE_VAULT_SEED, rate_data.key().as_ref(), oracle_pubkey.as_ref()],
+        bump = oracle_stake.vault_bump
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = rate_data.load()?.stake_mint)]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        address = rate_data.load()?.treasury @ ErrorCode::InvalidTreasury,
+        token::mint = stake_mint
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Context for funding a feed's rewards.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct FundRewards<'info> {
+    #[account(
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = rate_data.load()?.reward_mint != Pubkey::default()
+            @ ErrorCode::RewardsNotConfigured
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    // Owned by the rate_data PDA so only `claim_rewards` can pay out of it.
+    #[account(
+        init_if_needed,
+        payer = funder,
+        seeds = [REWARD_VAULT_SEED, rate_data.key().as_ref()],
+        bump,
+        token::mint = reward_mint,
+        token::authority = rate_data,
+        token::token_program = token_program
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = rate_data.load()?.reward_mint)]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = reward_mint)]
+    pub funder_tokens: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub funder: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for an oracle claiming its rewards.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct ClaimRewards<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(
+        mut,
+        seeds = [REWARD_VAULT_SEED, rate_data.key().as_ref()],
+        bump
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = rate_data.load()?.reward_mint)]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    // Where the rewards are paid. Any account of the reward mint will do.
+    #[account(mut, token::mint = reward_mint)]
+    pub oracle_tokens: InterfaceAccount<'info, TokenAccount>,
+    // The registered oracle claiming. Their signature is required.
+    pub oracle: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Context for delegating a feed to an ephemeral rollup.
+#[cfg(feature = "delegation")]
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct DelegateRateData<'info> {
+    /// CHECK: Emptied and handed to the delegation program, so it cannot be an
+    /// `AccountLoader`, which would write it back on exit. The owner and seeds are
+    /// checked here, the layout and authority by the handler.
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump
+    )]
+    pub rate_data: UncheckedAccount<'info>,
+    /// CHECK: Handed over like `rate_data`.
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [RATE_HISTORY_SEED, rate_data.key().as_ref()],
+        bump
+    )]
+    pub rate_history: UncheckedAccount<'info>,
+    /// CHECK: Holds the feed's data while it is handed over, then closed.
+    #[account(mut, seeds = [DELEGATE_BUFFER_SEED, rate_data.key().as_ref()], bump)]
+    pub rate_data_buffer: UncheckedAccount<'info>,
+    /// CHECK: Holds the history's data while it is handed over, then closed.
+    #[account(mut, seeds = [DELEGATE_BUFFER_SEED, rate_history.key().as_ref()], bump)]
+    pub rate_history_buffer: UncheckedAccount<'info>,
+    /// CHECK: Created by the delegation program.
+    #[account(
+        mut,
+        seeds = [DELEGATION_RECORD_SEED, rate_data.key().as_ref()],
+        bump,
+        seeds::program = DELEGATION_PROGRAM_ID
+    )]
+    pub rate_data_delegation_record: UncheckedAccount<'info>,
+    /// CHECK: Created by the delegation program.
+    #[account(
+        mut,
+        seeds = [DELEGATION_METADATA_SEED, rate_data.key().as_ref()],
+        bump,
+        seeds::program = DELEGATION_PROGRAM_ID
+    )]
+    pub rate_data_delegation_metadata: UncheckedAccount<'info>,
+    /// CHECK: Created by the delegation program.
+    #[account(
+        mut,
+        seeds = [DELEGATION_RECORD_SEED, rate_history.key().as_ref()],
+        bump,
+        seeds::program = DELEGATION_PROGRAM_ID
+    )]
+    pub rate_history_delegation_record: UncheckedAccount<'info>,
+    /// CHECK: Created by the delegation program.
+    #[account(
+        mut,
+        seeds = [DELEGATION_METADATA_SEED, rate_history.key().as_ref()],
+        bump,
+        seeds::program = DELEGATION_PROGRAM_ID
+    )]
+    pub rate_history_delegation_metadata: UncheckedAccount<'info>,
+    // The feed's authority. Pays for the delegation program's accounts.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub owner_program: Program<'info, program::ExchangeRateTracker>,
+    /// CHECK: The address is checked.
+    #[account(address = DELEGATION_PROGRAM_ID)]
+    pub delegation_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for committing a delegated feed to the base layer.
+#[cfg(feature = "delegation")]
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct CommitRateData<'info> {
+    #[account(
+        mut,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(mut, seeds = [RATE_HISTORY_SEED, rate_data.key().as_ref()], bump = rate_history.bump)]
+    pub rate_history: Account<'info, RateHistory>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: The address is checked. The magic program queues commits in it.
+    #[account(mut, address = MAGIC_CONTEXT_ID)]
+    pub magic_context: UncheckedAccount<'info>,
+    /// CHECK: The address is checked.
+    #[account(address = MAGIC_PROGRAM_ID)]
+    pub magic_program: UncheckedAccount<'info>,
+}
+
+// Context for returning a delegated feed to the base layer.
+#[cfg(feature = "delegation")]
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct UndelegateRateData<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump = rate_data.load()?.bump,
+        constraint = !rate_data.load()?.has_council() @ ErrorCode::CouncilApprovalRequired
+    )]
+    pub rate_data: AccountLoader<'info, RateData>,
+    #[account(mut, seeds = [RATE_HISTORY_SEED, rate_data.key().as_ref()], bump = rate_history.bump)]
+    pub rate_history: Account<'info, RateHistory>,
+    // The feed's authority. Pays for the commit.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: The address is checked. The magic program queues commits in it.
+    #[account(mut, address = MAGIC_CONTEXT_ID)]
+    pub magic_context: UncheckedAccount<'info>,
+    /// CHECK: The address is checked.
+    #[account(address = MAGIC_PROGRAM_ID)]
+    pub magic_program: UncheckedAccount<'info>,
+}
+
+// Context for the delegation program giving back an undelegated account.
+#[cfg(feature = "delegation")]
+#[derive(Accounts)]
+pub struct ProcessUndelegation<'info> {
+    /// CHECK: Closed by the delegation program and re-created by the handler,
+    /// which checks the seeds.
+    #[account(mut)]
+    pub delegated_account: UncheckedAccount<'info>,
+    // Only the delegation program can sign for its undelegation buffer, so this
+    // proves the call comes from it. Holds the account's committed data.
+    #[account(
+        owner = DELEGATION_PROGRAM_ID,
+        seeds = [UNDELEGATE_BUFFER_SEED, delegated_account.key().as_ref()],
+        bump,
+        seeds::program = DELEGATION_PROGRAM_ID
+    )]
+    pub buffer: Signer<'info>,
+    // Pays the re-created account's rent.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for migrating a feed to the zero-copy layout.
+#[derive(Accounts)]
+#[instruction(pair: CurrencyPair)]
+pub struct MigrateRateData<'info> {
+    /// CHECK: Still in the legacy Borsh layout, so it cannot be loaded as `RateData`.
+    /// The owner and seeds are checked here, the discriminator and authority by the handler.
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [RATE_DATA_SEED, pair.base.as_bytes(), PAIR_SEPARATOR, pair.quote.as_bytes()],
+        bump
+    )]
+    pub rate_data: UncheckedAccount<'info>,
+    // The feed's authority. Pays for any extra space.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// The account that stores the list of oracles and their data for one currency pair.
+// It is zero-copy so that `update_rate` only touches the bytes it changes instead
+// of deserializing and reserializing every oracle.
+#[account(zero_copy)]
+pub struct RateData {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,          // Proposed new authority, default if none
+    pub council: [Pubkey; MAX_COUNCIL_MEMBERS], // Admin council, see `admin`
+    pub stake_mint: Pubkey,                 // Mint oracles stake, default until configured
+    pub treasury: Pubkey,                   // Token account slashed stake is sent to
+    pub reward_mint: Pubkey,                // Mint oracles are rewarded in, unset until configured
+    pub base: [u8; MAX_CURRENCY_CODE_LEN],  // e.g., "USD", zero-padded
+    pub quote: [u8; MAX_CURRENCY_CODE_LEN], // e.g., "NGN", zero-padded
+    pub aggregate_rate: u64,          // Aggregate of the contributing oracles' rates, 0 if none
+    pub aggregate_timestamp: i64,     // Unix timestamp of the newest rate in the aggregate
+    pub aggregation_window_secs: i64, // Max age of an oracle's rate to count towards the aggregate
+    pub max_staleness_secs: i64,      // Max age of the aggregate that `get_rate` will return
+    pub min_update_interval_secs: i64, // Min seconds between an oracle's updates, unless overridden
+    pub price_cumulative: u64,        // Wrapping sum of aggregate_rate * seconds, see `twap`
+    pub live_secs_cumulative: u64,    // Seconds during which there was a non-zero aggregate
+    pub cumulative_timestamp: i64,    // Unix timestamp the accumulators were last brought up to
+    pub checkpoint_count: u64,        // Checkpoints ever written to `checkpoints`
+    pub next_proposal_id: u64,        // Id the next admin proposal must use
+    pub min_stake: u64,               // Bonded stake an oracle needs to update, see `staking`
+    pub unbonding_period_secs: i64,   // Seconds unstaked tokens stay slashable
+    pub total_staked: u64,            // Tokens across every stake vault of the feed
+    pub reward_per_update: u64,       // Reward accrued per accepted update, see `rewards`
+    pub max_rewards_per_epoch: u64,   // Most one oracle can accrue per epoch
+    pub commit_interval_secs: i64,    // Seconds between commits from a rollup, see `commit_policy`
+    pub last_commit_timestamp: i64,   // Unix timestamp of the last commit from a rollup, 0 if none
+    pub last_commit_rate: u64,        // Aggregate rate as of that commit
+    pub commit_every_updates: u32,    // Accepted updates that trigger a commit, 0 if none
+    pub updates_since_commit: u32,    // Accepted updates since the last commit
+    pub max_deviation_bps: u16,       // Max move of an update from its reference, 0 if unchecked
+    pub commit_deviation_bps: u16,    // Aggregate move since the last commit that triggers one
+    pub bump: u8,
+    pub exponent: u8,                 // Rates are stored scaled by 10^exponent
+    pub num_contributors: u8,         // Number of oracles that contributed to `aggregate_rate`
+    pub num_oracles: u8,              // Number of leading entries of `oracles` in use
+    pub council_size: u8,             // Number of leading entries of `council` in use, 0 if none
+    pub council_threshold: u8,        // Council approvals needed to execute a proposal
+    pub status: u8,                   // A `FeedStatus`; updates and reads fail while paused
+    pub aggregation_method: u8,       // An `AggregationMethod`, see `AggregationMethod::to_stored`
+    pub trim_pct: u8,                 // Percentage trimmed from each end by `TrimmedMean`
+    pub outlier_policy: u8,           // An `OutlierPolicy` for updates beyond the max deviation
+    pub padding: [u8; 2],
+    pub oracles: [Oracle; MAX_ORACLES],
+    pub checkpoints: [twap::Observation; MAX_CHECKPOINTS], // Ring of admin changes, see `twap`
+}
+
+impl RateData {
+    // Account size, including the discriminator.
+    pub const SPACE: usize = 8 + std::mem::size_of::<RateData>();
+
+    pub fn base(&self) -> &str {
+        str_from_fixed(&self.base)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.status == FeedStatus::Paused as u8
+    }
+
+    pub fn aggregation_method(&self) -> AggregationMethod {
+        AggregationMethod::from_stored(self.aggregation_method, self.trim_pct)
+            .unwrap_or(AggregationMethod::Median)
+    }
+
+    pub fn quote(&self) -> &str {
+        str_from_fixed(&self.quote)
+    }
+
+    // The registered oracles, in the order they were added.
+    pub fn active_oracles(&self) -> &[Oracle] {
+        &self.oracles[..self.num_oracles as usize]
+    }
+
+    pub fn find_oracle(&self, pubkey: &Pubkey) -> Option<usize> {
+        self.active_oracles().iter().position(|o| o.pubkey == *pubkey)
+    }
+
+    pub fn push_oracle(&mut self, oracle: Oracle) -> Result<()> {
+        let len = self.num_oracles as usize;
+        require!(len < MAX_ORACLES, ErrorCode::TooManyOracles);
+        self.oracles[len] = oracle;
+        self.num_oracles += 1;
+        Ok(())
+    }
+
+    // Removes the oracle at `index`, shifting later oracles down to keep the order.
+    pub fn remove_oracle(&mut self, index: usize) -> Oracle {
+        let len = self.num_oracles as usize;
+        let removed = self.oracles[index];
+        self.oracles.copy_within(index + 1..len, index);
+        self.oracles[len - 1] = Zeroable::zeroed();
+        self.num_oracles -= 1;
+        removed
+    }
+
+    // Recomputes the aggregate from every oracle that has published a non-zero
+    // rate within the aggregation window, using the feed's aggregation method.
+    // The aggregate is dated by the newest rate in it rather than `now`, so that
+    // admin and staking changes, which also recompute it, don't make old rates
+    // look fresh. The date is kept when no oracle contributes.
+    pub fn recompute_aggregate(&mut self, now: i64) {
+        let method = self.aggregation_method();
+        let mut samples = [(0u64, 0u64); MAX_ORACLES];
+        let mut count = 0;
+        let mut newest = None;
+        for oracle in self.active_oracles() {
+            if self.contributes(oracle, now) {
+                samples[count] = (oracle.rate, oracle.weight as u64);
+                count += 1;
+                newest = newest.max(Some(oracle.last_updated));
+            }
+        }
+
+        self.aggregate_rate = method.aggregate(&mut samples[..count]).unwrap_or(0);
+        self.num_contributors = count as u8;
+        if let Some(timestamp) = newest {
+            self.aggregate_timestamp = timestamp;
+        }
+    }
+
+    // Whether `oracle`'s rate is eligible for the aggregate at `now`: it has one,
+    // it is fresh, the oracle is neither halted nor below the minimum stake, and
+    // it has a weight if the feed's method uses them.
+    pub fn contributes(&self, oracle: &Oracle, now: i64) -> bool {
+        oracle.rate != 0
+            && !oracle.is_halted()
+            && now.saturating_sub(oracle.last_updated) <= self.aggregation_window_secs
+            && (oracle.weight > 0 || !self.aggregation_method().is_weighted())
+            && self.meets_min_stake(oracle.stake)
+    }
+
+    // Seconds `oracle` must wait between updates: its own override if it has one,
+    // otherwise the feed's.
+    pub fn min_update_interval(&self, oracle: &Oracle) -> i64 {
+        if oracle.has_interval_override != 0 {
+            oracle.min_update_interval_secs
+        } else {
+            self.min_update_interval_secs
+        }
+    }
+
+    // Whether `new_rate` from the oracle at `index` is further than
+    // `max_deviation_bps` from the aggregate. Once every rate in the aggregate
+    // has left the aggregation window, the oracle's own previous rate is the
+    // reference instead if it is still within the window, and otherwise nothing
+    // is, so a feed that went quiet can follow the market again. A feed's first
+    // rate is always accepted.
+    pub fn exceeds_deviation(&self, index: usize, new_rate: u64, now: i64) -> bool {
+        if self.max_deviation_bps == 0 {
+            return false;
+        }
+        let fresh = |timestamp: i64| now.saturating_sub(timestamp) <= self.aggregation_window_secs;
+        let oracle = &self.oracles[index];
+        let reference = if self.aggregate_rate != 0 && fresh(self.aggregate_timestamp) {
+            self.aggregate_rate
+        } else if fresh(oracle.last_updated) {
+            oracle.rate
+        } else {
+            0
+        };
+        reference != 0 && deviation_bps(new_rate, reference) > self.max_deviation_bps as u64
+    }
+}
+
+// Encodes `s` into a zero-padded fixed-size buffer. Callers check the length first.
+pub fn fixed_str<const N: usize>(s: &str) -> [u8; N] {
+    let mut buf = [0u8; N];
+    buf[..s.len()].copy_from_slice(s.as_bytes());
+    buf
+}
+
+// Decodes a buffer written by `fixed_str`.
+pub fn str_from_fixed(buf: &[u8]) -> &str {
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    std::str::from_utf8(&buf[..len]).unwrap_or_default()
+}
+
+// The rate returned by `get_rate`: 1 base = `rate` / 10^`exponent` quote.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RateQuote {
+    pub rate: u64,
+    pub exponent: u8,
+    pub timestamp: i64, // Unix timestamp of the newest rate in the aggregate
+}
+
+// The aggregate returned by `get_aggregate`, stale or not. Like the other
+// return types read over CPI, fields are only ever appended.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AggregateQuote {
+    pub rate: u64, // 0 if no oracle contributed
+    pub exponent: u8,
+    pub timestamp: i64, // Unix timestamp of the newest rate in the aggregate
+    pub num_contributors: u8,
+    pub num_oracles: u8,
+    pub method: AggregationMethod,
+    pub max_staleness_secs: i64,
+    pub last_commit_timestamp: i64, // Last commit from a rollup while delegated, 0 if none
+}
+
+// One oracle's entry in the list returned by `get_all_rates`. A full list of 16
+// stays within the 1KiB return data limit.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OracleRate {
+    pub pubkey: Pubkey,
+    pub rate: u64,
+    pub last_updated: i64,
+    pub weight: u16,
+    pub contributing: bool, // Whether the rate counts towards the aggregate
+}
+
+// The rate returned by `get_twap`: the average aggregate over the `window_secs`
+// seconds up to `timestamp`, scaled by 10^`exponent`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TwapQuote {
+    pub twap: u64,
+    pub exponent: u8,
+    pub window_secs: i64,
+    pub timestamp: i64,
+}
+
+// Whether a feed is accepting updates and serving reads, stored in `RateData.status`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FeedStatus {
+    Active = 0,
+    Paused = 1,
+}
+
+// What happens to an update beyond the feed's max deviation, stored in
+// `RateData.outlier_policy`. A failed transaction leaves no trace on-chain, so
+// only `Record` counts rejections towards the oracle's reputation.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OutlierPolicy {
+    Fail = 0,   // The update fails with `RateDeviationTooLarge`
+    Record = 1, // The update is dropped and counted in `Oracle.rejected_count`
+}
+
+// Identifies a feed by its currency codes: rates are quoted as 1 `base` = N `quote`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct CurrencyPair {
+    pub base: String,
+    pub quote: String,
+}
+
+impl CurrencyPair {
+    // Codes must be short uppercase alphanumerics (e.g. "USD", "USDT") so they
+    // are unambiguous as PDA seeds.
+    pub fn validate(&self) -> Result<()> {
+        for code in [&self.base, &self.quote] {
+            require!(
+                !code.is_empty()
+                    && code.len() <= MAX_CURRENCY_CODE_LEN
+                    && code.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()),
+                ErrorCode::InvalidCurrencyCode
+            );
+        }
+        require!(self.base != self.quote, ErrorCode::InvalidCurrencyCode);
+        Ok(())
+    }
+}
+
+// A fixed-capacity ring buffer of every update accepted for one currency pair.
+// Once `capacity` entries are stored, each new entry overwrites the oldest.
+#[account]
+pub struct RateHistory {
+    pub bump: u8,
+    pub capacity: u32,
+    pub head: u32,     // Index in `entries` the next update is written to
+    pub next_seq: u64, // Sequence number of the next update, i.e. the total ever appended
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl RateHistory {
+    pub const fn space(capacity: u32) -> usize {
+        8 + 1 + 4 + 4 + 8 + 4 + capacity as usize * HistoryEntry::SIZE
+    }
+
+    pub fn append(&mut self, entry: HistoryEntry) {
+        if self.entries.len() < self.capacity as usize {
+            self.entries.push(entry);
+        } else {
+            self.entries[self.head as usize] = entry;
+        }
+        self.head = (self.head + 1) % self.capacity;
+        self.next_seq += 1;
+    }
+
+    // Index in `entries` of the oldest retained entry.
+    fn oldest(&self) -> usize {
+        if self.entries.len() < self.capacity as usize {
+            0
+        } else {
+            self.head as usize
+        }
+    }
+
+    // The `i`th retained entry, oldest first.
+    pub fn entry(&self, i: usize) -> &HistoryEntry {
+        &self.entries[(self.oldest() + i) % self.entries.len()]
+    }
+
+    // Binary searches the retained entries, oldest first, for the first one that
+    // does not satisfy `pred`. `pred` must hold for a prefix of the history only.
+    pub fn partition_point(&self, pred: impl Fn(&HistoryEntry) -> bool) -> usize {
+        let (mut lo, mut hi) = (0, self.entries.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if pred(self.entry(mid)) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    // Retained entries from oldest to newest.
+    pub fn chronological(&self) -> impl Iterator<Item = &HistoryEntry> {
+        let (newer, older) = self.entries.split_at(self.oldest());
+        older.iter().chain(newer.iter())
+    }
+
+    pub fn range(&self, from_seq: u64, limit: u32) -> HistoryRange {
+        let first_retained = self.next_seq - self.entries.len() as u64;
+        let first_seq = from_seq.max(first_retained);
+        let entries = self
+            .chronological()
+            .skip((first_seq - first_retained) as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect();
+        HistoryRange { first_seq, entries }
+    }
+
+    // Rewrites the buffer in chronological order, dropping the oldest entries
+    // if they no longer fit.
+    pub fn set_capacity(&mut self, capacity: u32) {
+        let mut entries: Vec<HistoryEntry> = self.chronological().cloned().collect();
+        let excess = entries.len().saturating_sub(capacity as usize);
+        entries.drain(..excess);
+
+        self.head = entries.len() as u32 % capacity;
+        self.capacity = capacity;
+        self.entries = entries;
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct HistoryEntry {
+    pub timestamp: i64, // Unix timestamp of the update
+    pub oracle: Pubkey, // Key the oracle signed the update with
+    pub rate: u64,      // The submitted rate, scaled by the feed's exponent
+    pub price_cumulative: u64,     // `RateData.price_cumulative` as of `timestamp`
+    pub live_secs_cumulative: u64, // `RateData.live_secs_cumulative` as of `timestamp`
+}
+
+impl HistoryEntry {
+    pub const SIZE: usize = 8 + 32 + 8 + 8 + 8;
+
+    pub fn observation(&self) -> twap::Observation {
+        twap::Observation {
+            timestamp: self.timestamp,
+            price_cumulative: self.price_cumulative,
+            live_secs_cumulative: self.live_secs_cumulative,
+        }
+    }
+}
+
+// The entries returned by `get_history`; `first_seq` is the sequence number of
+// the first entry.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct HistoryRange {
+    pub first_seq: u64,
+    pub entries: Vec<HistoryEntry>,
+}
+
+// A pending admin change to a feed with a council. Closed when it is executed
+// or cancelled.
+#[account]
+pub struct AdminProposal {
+    pub rate_data: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub bump: u8,
+    pub action: AdminAction,
+    pub approvals: Vec<Pubkey>, // Council members who approved, including the proposer
+}
+
+impl AdminProposal {
+    pub fn space(action: &AdminAction) -> usize {
+        let action_len = action.try_to_vec().map_or(0, |data| data.len());
+        8 + 32 + 8 + 32 + 1 + action_len + 4 + MAX_COUNCIL_MEMBERS * 32
+    }
+
+    // Drops approvals from keys that have since left the council, so they don't
+    // count and the approvals always fit the space reserved for a full council.
+    pub fn prune_approvals(&mut self, rate_data: &RateData) {
+        self.approvals.retain(|key| rate_data.is_council_member(key));
+    }
+
+    // Approvals from keys that are still on the council.
+    pub fn approval_count(&self, rate_data: &RateData) -> usize {
+        self.approvals.iter().filter(|key| rate_data.is_council_member(key)).count()
+    }
+}
+
+// Represents a single data source (e.g., a bank, parallel market).
+#[zero_copy]
+pub struct Oracle {
+    pub name: [u8; MAX_ORACLE_NAME_LEN], // e.g., "Bank A", "Crypto Exchange", zero-padded
+    pub pubkey: Pubkey,     // The public key of the oracle allowed to update this rate
+    pub rate: u64,          // The rate scaled by the feed's exponent (e.g., 145025 for 1450.25)
+    pub last_updated: i64,  // Unix timestamp of the last update
+    pub halted: u8,         // Non-zero while the authority has halted this oracle
+    pub has_interval_override: u8, // Non-zero if `min_update_interval_secs` applies
+    pub weight: u16,        // Influence under the weighted aggregation methods
+    pub padding: [u8; 4],
+    pub min_update_interval_secs: i64, // Overrides the feed's minimum update interval
+    pub update_count: u64,          // Updates accepted from this oracle, see `reputation`
+    pub rejected_count: u64,        // Updates dropped as outliers
+    pub scored_update_count: u64,   // Accepted updates made while the feed had an aggregate
+    pub total_deviation_bps: u64,   // Sum of those updates' distance from the aggregate
+    pub stake: u64,                 // Mirrors the bonded stake of `pubkey`, see `staking`
+    pub accrued_rewards: u64,       // Rewards earned but not yet claimed, see `rewards`
+    pub reward_epoch: u64,          // Epoch `epoch_rewards` was earned in
+    pub epoch_rewards: u64,         // Rewards accrued in `reward_epoch`, up to the feed's cap
+}
+
+impl Oracle {
+    pub fn name(&self) -> &str {
+        str_from_fixed(&self.name)
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted != 0
+    }
+}
+
+
+// ========== EVENTS ==========
+
+#[event]
+pub struct TrackerInitialized {
+    pub rate_data: Pubkey,
+    pub authority: Pubkey,
+    pub base: String,
+    pub quote: String,
+    pub exponent: u8,
+}
+
+#[event]
+pub struct OracleAdded {
+    pub rate_data: Pubkey,
+    pub oracle: Pubkey,
+    pub name: String,
+}
+
+#[event]
+pub struct OracleRemoved {
+    pub rate_data: Pubkey,
+    pub oracle: Pubkey,
+    pub forfeited_rewards: u64, // Accrued rewards the oracle had not claimed
+}
+
+#[event]
+pub struct OracleKeyReplaced {
+    pub rate_data: Pubkey,
+    pub old_pubkey: Pubkey,
+    pub new_pubkey: Pubkey,
+}
+
+// Emitted for every admin action that only changes settings, carrying the
+// action as applied.
+#[event]
+pub struct SettingsChanged {
+    pub rate_data: Pubkey,
+    pub action: AdminAction,
+}
+
+// Emitted for every accepted update. `change_bps` is the change from the
+// oracle's previous rate, 0 for its first.
+#[event]
+pub struct RateUpdated {
+    pub rate_data: Pubkey,
+    pub oracle: Pubkey,
+    pub old_rate: u64,
+    pub new_rate: u64,
+    pub change_bps: i64,
+    pub aggregate_rate: u64, // The aggregate after this update
+    pub num_contributors: u8,
+    pub timestamp: i64,
+}
+
+// Emitted for an outlier dropped under `OutlierPolicy::Record`.
+#[event]
+pub struct RateRejected {
+    pub rate_data: Pubkey,
+    pub oracle: Pubkey,
+    pub rate: u64,
+    pub aggregate_rate: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct HistoryResized {
+    pub rate_data: Pubkey,
+    pub capacity: u32,
+}
+
+#[event]
+pub struct RateDataMigrated {
+    pub rate_data: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferProposed {
+    pub rate_data: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferAccepted {
+    pub rate_data: Pubkey,
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferCancelled {
+    pub rate_data: Pubkey,
+    pub authority: Pubkey,
+    pub cancelled_authority: Pubkey,
+}
+
+#[event]
+pub struct CouncilUpdated {
+    pub rate_data: Pubkey,
+    pub members: Vec<Pubkey>,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct AdminActionProposed {
+    pub rate_data: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub action: AdminAction,
+}
+
+#[event]
+pub struct AdminActionApproved {
+    pub rate_data: Pubkey,
+    pub proposal_id: u64,
+    pub member: Pubkey,
+    pub approvals: u8,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct AdminActionExecuted {
+    pub rate_data: Pubkey,
+    pub proposal_id: u64,
+}
+
+#[event]
+pub struct AdminActionCancelled {
+    pub rate_data: Pubkey,
+    pub proposal_id: u64,
+}
+
+#[event]
+pub struct FeedStatusChanged {
+    pub rate_data: Pubkey,
+    pub status: FeedStatus,
+}
+
+#[event]
+pub struct OracleHaltChanged {
+    pub rate_data: Pubkey,
+    pub oracle: Pubkey,
+    pub halted: bool,
+}
+
+#[event]
+pub struct StakeDeposited {
+    pub rate_data: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub bonded: u64,
+}
+
+#[event]
+pub struct UnstakeRequested {
+    pub rate_data: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub unbonding_until: i64,
+}
+
+#[event]
+pub struct StakeWithdrawn {
+    pub rate_data: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct StakeSynced {
+    pub rate_data: Pubkey,
+    pub staker: Pubkey,
+    pub bonded: u64,
+}
+
+#[event]
+pub struct SlashApproved {
+    pub rate_data: Pubkey,
+    pub oracle: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct OracleSlashed {
+    pub rate_data: Pubkey,
+    pub oracle: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct RewardsFunded {
+    pub rate_data: Pubkey,
+    pub funder: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub rate_data: Pubkey,
+    pub oracle: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct FeedDelegated {
+    pub rate_data: Pubkey,
+    pub validator: Option<Pubkey>,
+    pub commit_frequency_ms: u32,
+}
+
+#[event]
+pub struct FeedUndelegated {
+    pub rate_data: Pubkey,
+}
+
+#[event]
+pub struct CommitScheduled {
+    pub rate_data: Pubkey,
+    pub aggregate_rate: u64, // The aggregate being committed
+    pub timestamp: i64,
+}
+
+
+// ========== ERRORS ==========
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("The provided oracle is not authorized to update rates.")]
+    UnauthorizedOracle,
+    #[msg("An oracle with this public key already exists.")]
+    OracleAlreadyExists,
+    #[msg("No oracle with this public key is registered.")]
+    OracleNotFound,
+    #[msg("Currency codes must be 1-8 uppercase letters or digits and differ from each other.")]
+    InvalidCurrencyCode,
+    #[msg("The aggregation window must be a positive number of seconds.")]
+    InvalidAggregationWindow,
+    #[msg("The exponent must be at most 18.")]
+    InvalidExponent,
+    #[msg("Arithmetic overflow while converting a scaled rate.")]
+    MathOverflow,
+    #[msg("The max staleness must be a positive number of seconds.")]
+    InvalidMaxStaleness,
+    #[msg("The rate is stale or no oracle has contributed to it.")]
+    StaleRate,
+    #[msg("The history capacity must be between 1 and 144 entries.")]
+    InvalidHistoryCapacity,
+    #[msg("At most 15 history entries can be read at once.")]
+    HistoryReadTooLarge,
+    #[msg("The TWAP window must be a positive number of seconds.")]
+    InvalidTwapWindow,
+    #[msg("The TWAP window reaches further back than the retained history.")]
+    TwapWindowTooLong,
+    #[msg("Oracle names can be at most 32 bytes long.")]
+    OracleNameTooLong,
+    #[msg("This feed already has the maximum number of oracles.")]
+    TooManyOracles,
+    #[msg("This rate data account already uses the zero-copy layout.")]
+    AlreadyMigrated,
+    #[msg("The default public key cannot be made the authority.")]
+    InvalidAuthority,
+    #[msg("The signer is not the proposed authority for this feed.")]
+    NotPendingAuthority,
+    #[msg("There is no pending authority transfer to cancel.")]
+    NoPendingAuthority,
+    #[msg("Council members must be distinct and at most 7, with a threshold they can reach.")]
+    InvalidCouncil,
+    #[msg("This feed has a council, so admin changes must go through a proposal.")]
+    CouncilApprovalRequired,
+    #[msg("The signer is not a member of this feed's council.")]
+    NotCouncilMember,
+    #[msg("This feed has no council to approve proposals.")]
+    NoCouncil,
+    #[msg("The proposal id must be the feed's next proposal id.")]
+    InvalidProposalId,
+    #[msg("This council member has already approved the proposal.")]
+    AlreadyApproved,
+    #[msg("The proposal does not have enough council approvals yet.")]
+    NotEnoughApprovals,
+    #[msg("This feed is paused.")]
+    FeedPaused,
+    #[msg("This oracle has been halted by the feed's authority.")]
+    OracleHalted,
+    #[msg("The rate deviates too far from the feed's current rate.")]
+    RateDeviationTooLarge,
+    #[msg("The minimum update interval cannot be negative.")]
+    InvalidUpdateInterval,
+    #[msg("This oracle must wait longer before updating the rate again.")]
+    UpdateTooFrequent,
+    #[msg("A trimmed mean must trim less than 50% from each end.")]
+    InvalidTrimPercentage,
+    #[msg("Staking has not been configured for this feed.")]
+    StakingNotConfigured,
+    #[msg("The stake mint cannot change while tokens are staked.")]
+    StakeMintLocked,
+    #[msg("The unbonding period cannot be negative.")]
+    InvalidUnbondingPeriod,
+    #[msg("The stake amount must be positive.")]
+    InvalidStakeAmount,
+    #[msg("The oracle does not have enough stake.")]
+    InsufficientStake,
+    #[msg("There is no unbonded stake to withdraw.")]
+    NothingToWithdraw,
+    #[msg("The stake is still unbonding.")]
+    StakeStillUnbonding,
+    #[msg("No slash has been approved against this stake.")]
+    NoSlashApproved,
+    #[msg("The treasury must be the feed's configured treasury.")]
+    InvalidTreasury,
+    #[msg("Rewards have not been configured for this feed.")]
+    RewardsNotConfigured,
+    #[msg("The reward mint cannot change once it is set.")]
+    RewardMintLocked,
+    #[msg("There are no rewards to claim, or the reward vault is empty.")]
+    NothingToClaim,
+    #[msg("Too few oracles contributed to the aggregate.")]
+    NotEnoughOracles,
+    #[msg("The commit interval cannot be negative.")]
+    InvalidCommitInterval,
+    #[msg("The stake account does not belong to the slashed key on this feed.")]
+    InvalidOracleStake,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::{Discriminator, Event};
+
+    // Splits an event as it appears in a `Program data:` log line into its
+    // discriminator and fields.
+    fn decode<T: Event + Discriminator + AnchorDeserialize>(data: &[u8]) -> T {
+        assert_eq!(&data[..8], T::DISCRIMINATOR);
+        T::try_from_slice(&data[8..]).unwrap()
+    }
+
+    #[test]
+    fn rate_updated_has_a_fixed_layout() {
+        let (rate_data, oracle) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = RateUpdated {
+            rate_data,
+            oracle,
+            old_rate: 145_000,
+            new_rate: 146_450,
+            change_bps: change_bps(145_000, 146_450),
+            aggregate_rate: 145_725,
+            num_contributors: 2,
+            timestamp: 1_700_000_000,
+        }
+        .data();
+
+        // Indexers may read fields at fixed offsets, so the layout must not move.
+        assert_eq!(data.len(), 8 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 8);
+        assert_eq!(&data[8..40], rate_data.as_ref());
+        assert_eq!(data[88..96], 100i64.to_le_bytes());
+
+        let event: RateUpdated = decode(&data);
+        assert_eq!((event.oracle, event.old_rate, event.new_rate), (oracle, 145_000, 146_450));
+        assert_eq!((event.change_bps, event.num_contributors), (100, 2));
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let rate_data = Pubkey::new_unique();
+        let data = TrackerInitialized {
+            rate_data,
+            authority: Pubkey::new_unique(),
+            base: "USD".to_string(),
+            quote: "NGN".to_string(),
+            exponent: 2,
+        }
+        .data();
+        let event: TrackerInitialized = decode(&data);
+        assert_eq!((event.base.as_str(), event.quote.as_str(), event.exponent), ("USD", "NGN", 2));
+
+        let data = SettingsChanged {
+            rate_data,
+            action: AdminAction::SetMaxDeviation { max_deviation_bps: 250 },
+        }
+        .data();
+        let event: SettingsChanged = decode(&data);
+        assert!(matches!(event.action, AdminAction::SetMaxDeviation { max_deviation_bps: 250 }));
+
+        // Every event has its own discriminator.
+        assert_ne!(RateUpdated::DISCRIMINATOR, RateRejected::DISCRIMINATOR);
+        assert_ne!(OracleAdded::DISCRIMINATOR, OracleRemoved::DISCRIMINATOR);
+    }
+
+    #[test]
+    fn deviation_falls_back_once_the_aggregate_is_stale() {
+        let mut rate_data = RateData::zeroed();
+        rate_data.max_deviation_bps = 100;
+        rate_data.aggregation_window_secs = 300;
+        rate_data.aggregate_rate = 150_000;
+        rate_data.aggregate_timestamp = 1_000;
+        rate_data
+            .push_oracle(Oracle { rate: 150_000, last_updated: 1_000, ..Zeroable::zeroed() })
+            .unwrap();
+
+        // While the aggregate is fresh, it is the reference.
+        assert!(rate_data.exceeds_deviation(0, 160_000, 1_300));
+        assert!(!rate_data.exceeds_deviation(0, 151_000, 1_300));
+
+        // Once it is stale, the oracle's own rate is, if that is still fresh.
+        rate_data.oracles[0] = Oracle { rate: 158_000, last_updated: 1_200, ..Zeroable::zeroed() };
+        assert!(!rate_data.exceeds_deviation(0, 159_000, 1_400));
+        assert!(rate_data.exceeds_deviation(0, 150_000, 1_400));
+
+        // With nothing fresh to compare against, any rate is accepted.
+        assert!(!rate_data.exceeds_deviation(0, 200_000, 1_600));
+    }
+
+    #[test]
+    fn approvals_fit_after_the_council_changes() {
+        let mut rate_data = RateData::zeroed();
+        let seat = |rate_data: &mut RateData| {
+            for member in &mut rate_data.council {
+                *member = Pubkey::new_unique();
+            }
+            rate_data.council_size = MAX_COUNCIL_MEMBERS as u8;
+        };
+        seat(&mut rate_data);
+
+        let action = AdminAction::ApplyReputationWeights;
+        let mut proposal = AdminProposal {
+            rate_data: Pubkey::new_unique(),
+            id: 0,
+            proposer: rate_data.council[0],
+            bump: 0,
+            action: action.clone(),
+            approvals: rate_data.council.to_vec(),
+        };
+
+        // A whole new council approves; the old one's approvals make way.
+        seat(&mut rate_data);
+        for member in rate_data.council {
+            proposal.prune_approvals(&rate_data);
+            proposal.approvals.push(member);
+        }
+        assert_eq!(proposal.approvals, rate_data.council);
+        assert_eq!(proposal.approval_count(&rate_data), MAX_COUNCIL_MEMBERS);
+        assert!(8 + proposal.try_to_vec().unwrap().len() <= AdminProposal::space(&action));
+    }
 }
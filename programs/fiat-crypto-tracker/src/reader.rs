@@ -0,0 +1,43 @@
+// Typed helpers for reading a feed from another program, built with the `cpi`
+// feature.
+//
+// Each helper invokes one of the read-only instructions and decodes its return
+// data, so consumers get the same checks the tracker applies to its own reads
+// (the feed's PDA, pauses and, for `get_rate`, staleness) without depending on
+// the `RateData` layout. `program` must be the tracker program's account and
+// `rate_data` the pair's feed.
+
+use anchor_lang::prelude::*;
+
+use crate::cpi::accounts::ReadRate;
+use crate::{AggregateQuote, CurrencyPair, OracleRate, RateQuote};
+
+// The pair's aggregate, failing if there is none or it is stale.
+pub fn get_rate<'info>(
+    program: AccountInfo<'info>,
+    rate_data: AccountInfo<'info>,
+    pair: CurrencyPair,
+) -> Result<RateQuote> {
+    let ctx = CpiContext::new(program, ReadRate { rate_data });
+    Ok(crate::cpi::get_rate(ctx, pair)?.get())
+}
+
+// The pair's aggregate with how it was formed, stale or not.
+pub fn get_aggregate<'info>(
+    program: AccountInfo<'info>,
+    rate_data: AccountInfo<'info>,
+    pair: CurrencyPair,
+) -> Result<AggregateQuote> {
+    let ctx = CpiContext::new(program, ReadRate { rate_data });
+    Ok(crate::cpi::get_aggregate(ctx, pair)?.get())
+}
+
+// Every oracle's latest rate for the pair.
+pub fn get_all_rates<'info>(
+    program: AccountInfo<'info>,
+    rate_data: AccountInfo<'info>,
+    pair: CurrencyPair,
+) -> Result<Vec<OracleRate>> {
+    let ctx = CpiContext::new(program, ReadRate { rate_data });
+    Ok(crate::cpi::get_all_rates(ctx, pair)?.get())
+}
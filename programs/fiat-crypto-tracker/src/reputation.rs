@@ -0,0 +1,129 @@
+// Per-oracle reputation: how often an oracle updates, how far its rates sit from
+// the consensus it was updating against, and how many of its submissions were
+// dropped as outliers.
+//
+// Distance from consensus is measured in basis points of the aggregate in effect
+// just before each update, so feeds with different exponents and price levels
+// are comparable. Updates made while the feed had no aggregate are counted but
+// not scored.
+
+use anchor_lang::prelude::*;
+
+use crate::fixed_point::deviation_bps;
+use crate::{Oracle, RateData};
+
+// Weight `reputation_weight` gives an oracle that always matched consensus.
+pub const MAX_REPUTATION_WEIGHT: u64 = 100;
+
+// One row of the table returned by `get_reputation`. A full table of 16 rows
+// stays within the 1KiB return data limit.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OracleReputation {
+    pub pubkey: Pubkey,
+    pub update_count: u64,
+    pub rejected_count: u64,
+    pub mean_abs_deviation_bps: u64,
+}
+
+impl Oracle {
+    // Records an accepted update of `rate` against the aggregate `consensus`,
+    // which is 0 if the feed had none.
+    pub fn record_update(&mut self, rate: u64, consensus: u64) {
+        self.update_count += 1;
+        if consensus != 0 {
+            self.scored_update_count += 1;
+            self.total_deviation_bps =
+                self.total_deviation_bps.saturating_add(deviation_bps(rate, consensus));
+        }
+    }
+
+    // Mean distance from consensus over the scored updates, `None` if there are none.
+    pub fn mean_abs_deviation_bps(&self) -> Option<u64> {
+        self.total_deviation_bps.checked_div(self.scored_update_count)
+    }
+
+    // An aggregation weight derived from the oracle's record, `None` until it has
+    // a scored update. The weight is inversely proportional to 100 bps plus the
+    // mean deviation from consensus, so it halves at 100 bps, and is scaled down
+    // by the share of submissions dropped as outliers.
+    pub fn reputation_weight(&self) -> Option<u16> {
+        let mean = self.mean_abs_deviation_bps()? as u128;
+        let accepted = self.update_count as u128;
+        let submitted = accepted + self.rejected_count as u128;
+        let weight = MAX_REPUTATION_WEIGHT as u128 * 100 * accepted / ((100 + mean) * submitted);
+        Some(weight as u16)
+    }
+
+    pub fn reputation(&self) -> OracleReputation {
+        OracleReputation {
+            pubkey: self.pubkey,
+            update_count: self.update_count,
+            rejected_count: self.rejected_count,
+            mean_abs_deviation_bps: self.mean_abs_deviation_bps().unwrap_or(0),
+        }
+    }
+}
+
+impl RateData {
+    // Every oracle's reputation, closest to consensus first. Ties go to the oracle
+    // with fewer rejections, then more updates; unscored oracles come last.
+    pub fn reputation_table(&self) -> Vec<OracleReputation> {
+        let mut oracles: Vec<&Oracle> = self.active_oracles().iter().collect();
+        oracles.sort_by_key(|o| {
+            (
+                o.mean_abs_deviation_bps().is_none(),
+                o.mean_abs_deviation_bps(),
+                o.rejected_count,
+                std::cmp::Reverse(o.update_count),
+            )
+        });
+        oracles.into_iter().map(Oracle::reputation).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::Zeroable;
+
+    fn oracle(updates: &[(u64, u64)], rejected: u64) -> Oracle {
+        let mut oracle = Oracle { pubkey: Pubkey::new_unique(), ..Zeroable::zeroed() };
+        for &(rate, consensus) in updates {
+            oracle.record_update(rate, consensus);
+        }
+        oracle.rejected_count = rejected;
+        oracle
+    }
+
+    #[test]
+    fn scores_only_updates_with_a_consensus() {
+        let oracle = oracle(&[(100, 0), (105, 100), (99, 100)], 0);
+        assert_eq!(oracle.update_count, 3);
+        assert_eq!(oracle.mean_abs_deviation_bps(), Some(300));
+        assert_eq!(self::oracle(&[(100, 0)], 0).mean_abs_deviation_bps(), None);
+    }
+
+    #[test]
+    fn weight_falls_with_deviation_and_rejections() {
+        assert_eq!(oracle(&[(100, 100)], 0).reputation_weight(), Some(100));
+        assert_eq!(oracle(&[(101, 100)], 0).reputation_weight(), Some(50));
+        assert_eq!(oracle(&[(100, 100)], 1).reputation_weight(), Some(50));
+        assert_eq!(oracle(&[(100, 0)], 0).reputation_weight(), None);
+        assert_eq!(oracle(&[(u64::MAX, 1)], 0).reputation_weight(), Some(0));
+    }
+
+    #[test]
+    fn ranks_closest_to_consensus_first() {
+        let mut rate_data = RateData::zeroed();
+        let unscored = oracle(&[(100, 0)], 0);
+        let far = oracle(&[(110, 100)], 0);
+        let close_but_rejected = oracle(&[(101, 100)], 2);
+        let close = oracle(&[(101, 100)], 0);
+        for o in [unscored, far, close_but_rejected, close] {
+            rate_data.push_oracle(o).unwrap();
+        }
+
+        let ranked: Vec<Pubkey> = rate_data.reputation_table().iter().map(|r| r.pubkey).collect();
+        assert_eq!(ranked, [close.pubkey, close_but_rejected.pubkey, far.pubkey, unscored.pubkey]);
+    }
+}
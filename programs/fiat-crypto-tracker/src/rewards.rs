@@ -0,0 +1,49 @@
+// Oracle rewards.
+//
+// A feed's authority picks a reward mint (the native mint for SOL), a reward per
+// accepted update and a cap on what one oracle can earn per epoch. Consumers or
+// the authority fund a vault, a token account PDA owned by the feed's `rate_data`
+// PDA, and every accepted `update_rate` accrues the reward to the oracle until it
+// hits the cap for the current epoch. Oracles claim what they have accrued with
+// `claim_rewards`; if the vault runs short they are paid what it holds and the
+// rest stays owed. Rewards belong to the oracle rather than its key, so they
+// survive `replace_oracle_key`. Removing an oracle forfeits whatever it has not
+// claimed, which stays in the vault; `OracleRemoved` reports the amount.
+
+use crate::Oracle;
+
+impl Oracle {
+    // Accrues `reward` for an accepted update in `epoch`, up to `max_per_epoch`
+    // in total for the epoch. Returns the amount accrued.
+    pub fn accrue_reward(&mut self, reward: u64, max_per_epoch: u64, epoch: u64) -> u64 {
+        if self.reward_epoch != epoch {
+            self.reward_epoch = epoch;
+            self.epoch_rewards = 0;
+        }
+        let accrued = reward.min(max_per_epoch.saturating_sub(self.epoch_rewards));
+        self.epoch_rewards += accrued;
+        self.accrued_rewards = self.accrued_rewards.saturating_add(accrued);
+        accrued
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::Zeroable;
+
+    #[test]
+    fn caps_rewards_per_epoch() {
+        let mut oracle = Oracle::zeroed();
+        assert_eq!(oracle.accrue_reward(40, 100, 7), 40);
+        assert_eq!(oracle.accrue_reward(40, 100, 7), 40);
+        // Only 20 left under the cap, then nothing.
+        assert_eq!(oracle.accrue_reward(40, 100, 7), 20);
+        assert_eq!(oracle.accrue_reward(40, 100, 7), 0);
+        assert_eq!(oracle.accrued_rewards, 100);
+
+        // A new epoch resets the cap but not what is owed.
+        assert_eq!(oracle.accrue_reward(40, 100, 8), 40);
+        assert_eq!(oracle.accrued_rewards, 140);
+    }
+}
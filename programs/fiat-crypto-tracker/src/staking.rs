@@ -0,0 +1,159 @@
+// Oracle staking and slashing.
+//
+// A feed's authority picks a stake mint, a treasury token account, a minimum
+// stake and an unbonding period. Any key can stake into its own vault: a token
+// account PDA owned by the feed's `rate_data` PDA, tracked by an `OracleStake`
+// account. A registered oracle whose bonded stake is below the minimum cannot
+// update and is left out of the aggregate; `Oracle.stake` mirrors the bonded
+// amount so `update_rate` doesn't need the stake accounts. Staking keeps it in
+// step, but an oracle added (or a key rotated in) after staking starts at 0
+// until anyone calls `sync_stake`.
+//
+// Unstaking is two steps: the requested amount stops counting straight away but
+// can only be withdrawn once the unbonding period has passed, and it can still
+// be slashed until then. Slashing is also two steps: the authority (or council)
+// approves an amount against a key's stake, then anyone can move it to the
+// treasury. The approval is kept on the `OracleStake`, so a key that has been
+// rotated out or removed as an oracle can still be slashed.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, TransferChecked};
+
+use crate::{CurrencyPair, RateData, PAIR_SEPARATOR, RATE_DATA_SEED};
+
+// Tracks one key's stake in a feed. The tokens sit in the matching vault.
+#[account]
+pub struct OracleStake {
+    pub rate_data: Pubkey,
+    pub staker: Pubkey,
+    pub bump: u8,
+    pub vault_bump: u8,
+    pub amount: u64,           // Bonded stake, counted towards the feed's minimum
+    pub unbonding_amount: u64, // Stake requested for withdrawal, still slashable
+    pub unbonding_until: i64,  // Unix timestamp `unbonding_amount` can be withdrawn from
+    pub slash_approved: u64,   // Stake `slash` may move to the treasury
+}
+
+impl OracleStake {
+    pub const SPACE: usize = 8 + 32 + 32 + 1 + 1 + 8 + 8 + 8 + 8;
+
+    // Takes up to `amount`, from bonded stake first and then from stake that is
+    // unbonding. Returns how much was taken.
+    pub fn slash(&mut self, amount: u64) -> u64 {
+        let from_bonded = amount.min(self.amount);
+        let from_unbonding = (amount - from_bonded).min(self.unbonding_amount);
+        self.amount -= from_bonded;
+        self.unbonding_amount -= from_unbonding;
+        from_bonded + from_unbonding
+    }
+}
+
+impl RateData {
+    // Whether `stake` meets the feed's minimum for an oracle to be active.
+    pub fn meets_min_stake(&self, stake: u64) -> bool {
+        stake >= self.min_stake
+    }
+
+    // Mirrors `staker`'s bonded stake onto its oracle, if it is one, and
+    // recomputes the aggregate in case the oracle crossed the minimum.
+    pub fn sync_stake(&mut self, staker: &Pubkey, bonded: u64, now: i64) {
+        if let Some(index) = self.find_oracle(staker) {
+            self.oracles[index].stake = bonded;
+            self.refresh_aggregate(now);
+        }
+    }
+}
+
+// Transfers `amount` out of a stake vault. Vaults are owned by the feed's
+// `rate_data` PDA, which signs with `pair` and its `bump`.
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_from_vault<'info>(
+    token_program: AccountInfo<'info>,
+    vault: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    rate_data: AccountInfo<'info>,
+    pair: &CurrencyPair,
+    bump: u8,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[
+        RATE_DATA_SEED,
+        pair.base.as_bytes(),
+        PAIR_SEPARATOR,
+        pair.quote.as_bytes(),
+        &[bump],
+    ];
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program,
+            TransferChecked { from: vault, mint, to, authority: rate_data },
+            &[seeds],
+        ),
+        amount,
+        decimals,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::AdminAction;
+    use bytemuck::Zeroable;
+
+    fn stake(amount: u64, unbonding_amount: u64) -> OracleStake {
+        OracleStake {
+            rate_data: Pubkey::default(),
+            staker: Pubkey::default(),
+            bump: 0,
+            vault_bump: 0,
+            amount,
+            unbonding_amount,
+            unbonding_until: 0,
+            slash_approved: 0,
+        }
+    }
+
+    #[test]
+    fn slashes_bonded_stake_before_unbonding_stake() {
+        let mut s = stake(100, 50);
+        assert_eq!(s.slash(30), 30);
+        assert_eq!((s.amount, s.unbonding_amount), (70, 50));
+
+        assert_eq!(s.slash(100), 100);
+        assert_eq!((s.amount, s.unbonding_amount), (0, 20));
+    }
+
+    #[test]
+    fn slashes_no_more_than_is_staked() {
+        let mut s = stake(10, 5);
+        assert_eq!(s.slash(u64::MAX), 15);
+        assert_eq!((s.amount, s.unbonding_amount), (0, 0));
+        assert_eq!(s.slash(1), 0);
+    }
+
+    #[test]
+    fn approves_slashes_against_keys_that_are_no_longer_oracles() {
+        let mut rate_data = RateData::zeroed();
+        let rate_data_key = Pubkey::new_unique();
+        let staker = Pubkey::new_unique();
+        let mut s = stake(100, 0);
+        s.rate_data = rate_data_key;
+        s.staker = staker;
+
+        let approve = |rate_data: &mut RateData, s: &mut OracleStake, pubkey: Pubkey| {
+            let action = AdminAction::ApproveSlash { pubkey, amount: 40 };
+            action.apply(rate_data, rate_data_key, 0, Some(s))
+        };
+        approve(&mut rate_data, &mut s, staker).unwrap();
+        approve(&mut rate_data, &mut s, staker).unwrap();
+        assert_eq!(s.slash_approved, 80);
+
+        // The approval must land on the stake of the key it names.
+        assert!(approve(&mut rate_data, &mut s, Pubkey::new_unique()).is_err());
+        let action = AdminAction::ApproveSlash { pubkey: staker, amount: 40 };
+        assert!(action.apply(&mut rate_data, rate_data_key, 0, None).is_err());
+        assert_eq!(s.slash_approved, 80);
+    }
+}
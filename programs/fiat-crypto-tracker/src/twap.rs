@@ -0,0 +1,248 @@
+// Time-weighted average price support.
+//
+// `RateData` keeps two running accumulators: the sum of `aggregate_rate * seconds`
+// and the number of seconds during which an aggregate existed at all. Both are
+// checkpointed into every history entry, so the TWAP over any window the history
+// still covers is the difference between two observations, found by binary
+// search rather than by scanning the history. The price accumulator wraps on
+// overflow; differences taken with `wrapping_sub` stay exact as long as a single
+// window's total fits in a u64.
+//
+// Admin and staking changes can move the aggregate without an update, and so
+// without a history entry. Those changes are checkpointed into a small ring on
+// `RateData` instead, so that no two neighbouring checkpoints ever straddle a
+// change and interpolating between them stays exact.
+
+use anchor_lang::prelude::*;
+
+use crate::{ErrorCode, RateData, RateHistory, MAX_CHECKPOINTS};
+
+// The accumulators as of `timestamp`.
+#[zero_copy]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Observation {
+    pub timestamp: i64,
+    pub price_cumulative: u64,
+    pub live_secs_cumulative: u64,
+}
+
+impl RateData {
+    // Brings the accumulators up to `now` using the aggregate in effect since the
+    // last checkpoint. Must be called before the aggregate changes.
+    pub fn accumulate(&mut self, now: i64) {
+        let observation = self.observe(now);
+        self.price_cumulative = observation.price_cumulative;
+        self.live_secs_cumulative = observation.live_secs_cumulative;
+        self.cumulative_timestamp = observation.timestamp;
+    }
+
+    // The accumulators as they would be at `at`, which must not be earlier than
+    // the last checkpoint.
+    pub fn observe(&self, at: i64) -> Observation {
+        let elapsed = at.saturating_sub(self.cumulative_timestamp).max(0) as u64;
+        let (price, live) = if self.aggregate_rate == 0 {
+            (0, 0)
+        } else {
+            (self.aggregate_rate.wrapping_mul(elapsed), elapsed)
+        };
+        Observation {
+            timestamp: at,
+            price_cumulative: self.price_cumulative.wrapping_add(price),
+            live_secs_cumulative: self.live_secs_cumulative.wrapping_add(live),
+        }
+    }
+
+    fn checkpoint(&self) -> Observation {
+        Observation {
+            timestamp: self.cumulative_timestamp,
+            price_cumulative: self.price_cumulative,
+            live_secs_cumulative: self.live_secs_cumulative,
+        }
+    }
+
+    // Recomputes the aggregate at `now` outside of `update_rate`, checkpointing
+    // the accumulators if that changes it.
+    pub fn refresh_aggregate(&mut self, now: i64) {
+        self.accumulate(now);
+        let previous = self.aggregate_rate;
+        self.recompute_aggregate(now);
+        if self.aggregate_rate != previous {
+            let slot = (self.checkpoint_count % MAX_CHECKPOINTS as u64) as usize;
+            self.checkpoints[slot] = self.checkpoint();
+            self.checkpoint_count += 1;
+        }
+    }
+
+    // The checkpoints in the ring that have not been overwritten, in no order.
+    fn retained_checkpoints(&self) -> &[Observation] {
+        &self.checkpoints[..self.checkpoint_count.min(MAX_CHECKPOINTS as u64) as usize]
+    }
+
+    // The accumulators at any time from the oldest retained checkpoint up to
+    // `now`, interpolating between checkpoints.
+    pub fn observe_at(&self, history: &RateHistory, at: i64) -> Result<Observation> {
+        if at >= self.cumulative_timestamp {
+            return Ok(self.observe(at));
+        }
+
+        let len = history.entries.len();
+        let observation = |i: usize| history.entry(i).observation();
+
+        // The last checkpoint at or before `at` and the first one after it, from
+        // either the history or the ring.
+        let after = history.partition_point(|e| e.timestamp <= at);
+        let mut before = after.checked_sub(1).map(observation);
+        let mut next = if after < len { observation(after) } else { self.checkpoint() };
+        for &checkpoint in self.retained_checkpoints() {
+            if checkpoint.timestamp > at {
+                if checkpoint.timestamp < next.timestamp {
+                    next = checkpoint;
+                }
+            } else if before.is_none_or(|b| checkpoint.timestamp > b.timestamp) {
+                before = Some(checkpoint);
+            }
+        }
+        let before = before.ok_or(ErrorCode::TwapWindowTooLong)?;
+
+        // Whatever either source has overwritten is older than its oldest retained
+        // checkpoint, so it can't lie between `before` and `next` if that is older
+        // than `before`.
+        let history_covers =
+            history.next_seq == len as u64 || observation(0).timestamp <= before.timestamp;
+        let ring_covers = self.checkpoint_count <= MAX_CHECKPOINTS as u64 || {
+            let oldest = (self.checkpoint_count % MAX_CHECKPOINTS as u64) as usize;
+            self.checkpoints[oldest].timestamp <= before.timestamp
+        };
+        require!(history_covers && ring_covers, ErrorCode::TwapWindowTooLong);
+        Ok(interpolate(before, next, at))
+    }
+}
+
+// The accumulators at `at`, given the checkpoints either side of it. Exact as long
+// as the aggregate did not change between the two.
+pub fn interpolate(before: Observation, after: Observation, at: i64) -> Observation {
+    let span = (after.timestamp - before.timestamp) as u128;
+    if span == 0 {
+        return before;
+    }
+    let elapsed = (at - before.timestamp) as u128;
+    let scale = |from: u64, to: u64| {
+        let delta = to.wrapping_sub(from) as u128;
+        from.wrapping_add((delta * elapsed / span) as u64)
+    };
+    Observation {
+        timestamp: at,
+        price_cumulative: scale(before.price_cumulative, after.price_cumulative),
+        live_secs_cumulative: scale(before.live_secs_cumulative, after.live_secs_cumulative),
+    }
+}
+
+// The time-weighted average aggregate between two observations, ignoring time
+// during which there was no aggregate. `None` if there was none for the whole span.
+pub fn twap(start: Observation, end: Observation) -> Option<u64> {
+    let live = end.live_secs_cumulative.wrapping_sub(start.live_secs_cumulative);
+    if live == 0 {
+        return None;
+    }
+    Some(end.price_cumulative.wrapping_sub(start.price_cumulative) / live)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::AdminAction;
+    use crate::aggregation::AggregationMethod;
+    use crate::{HistoryEntry, Oracle};
+    use bytemuck::Zeroable;
+
+    fn obs(timestamp: i64, price_cumulative: u64, live_secs_cumulative: u64) -> Observation {
+        Observation { timestamp, price_cumulative, live_secs_cumulative }
+    }
+
+    #[test]
+    fn interpolates_between_checkpoints() {
+        // 100 for 10 seconds between the two checkpoints.
+        let at = interpolate(obs(10, 500, 5), obs(20, 1500, 15), 14);
+        assert_eq!(at, obs(14, 900, 9));
+    }
+
+    #[test]
+    fn twap_weights_by_time_and_skips_dead_time() {
+        // 100 for 30s, then 200 for 10s.
+        assert_eq!(twap(obs(0, 0, 0), obs(40, 5000, 40)), Some(125));
+        // Same prices with 20s of no aggregate in between.
+        assert_eq!(twap(obs(0, 0, 0), obs(60, 5000, 40)), Some(125));
+        assert_eq!(twap(obs(0, 7, 3), obs(60, 7, 3)), None);
+    }
+
+    // Records an update from `oracle` at `now` the way `update_rate` does.
+    fn update(
+        rate_data: &mut RateData,
+        history: &mut RateHistory,
+        oracle: Pubkey,
+        rate: u64,
+        now: i64,
+    ) {
+        rate_data.accumulate(now);
+        history.append(HistoryEntry {
+            timestamp: now,
+            oracle,
+            rate,
+            price_cumulative: rate_data.price_cumulative,
+            live_secs_cumulative: rate_data.live_secs_cumulative,
+        });
+        let index = rate_data.find_oracle(&oracle).unwrap();
+        rate_data.oracles[index].rate = rate;
+        rate_data.oracles[index].last_updated = now;
+        rate_data.recompute_aggregate(now);
+    }
+
+    #[test]
+    fn admin_changes_to_the_aggregate_are_checkpointed() {
+        let mut rate_data = RateData::zeroed();
+        rate_data.aggregation_window_secs = 300;
+        (rate_data.aggregation_method, rate_data.trim_pct) = AggregationMethod::Min.to_stored();
+        let (low, high) = (Pubkey::new_unique(), Pubkey::new_unique());
+        for pubkey in [low, high] {
+            rate_data.push_oracle(Oracle { pubkey, weight: 1, ..Zeroable::zeroed() }).unwrap();
+        }
+        let mut history =
+            RateHistory { bump: 0, capacity: 8, head: 0, next_seq: 0, entries: Vec::new() };
+        let halt = |rate_data: &mut RateData, halted: bool, now: i64| {
+            let action = AdminAction::SetOracleHalted { pubkey: low, halted };
+            action.apply(rate_data, Pubkey::new_unique(), now, None).unwrap();
+        };
+
+        // 100 until the low oracle is halted at 50, then 300. The next update
+        // only comes at 80, so the history alone can't place the change.
+        update(&mut rate_data, &mut history, low, 100, 0);
+        update(&mut rate_data, &mut history, high, 300, 0);
+        halt(&mut rate_data, true, 50);
+        assert_eq!(rate_data.aggregate_rate, 300);
+        update(&mut rate_data, &mut history, high, 300, 80);
+
+        let end = rate_data.observe(100);
+        let start = rate_data.observe_at(&history, 60).unwrap();
+        assert_eq!(twap(start, end), Some(300));
+        let start = rate_data.observe_at(&history, 25).unwrap();
+        assert_eq!(twap(start, end), Some((100 * 25 + 300 * 50) / 75));
+
+        // Once the change at 50 has been overwritten, windows that reach back
+        // past it are refused, while shorter ones are still exact.
+        for now in 81..=96 {
+            halt(&mut rate_data, now % 2 == 0, now);
+        }
+        assert_eq!(rate_data.aggregate_rate, 300);
+        let end = rate_data.observe(100);
+        assert!(rate_data.observe_at(&history, 60).is_err());
+        let start = rate_data.observe_at(&history, 94).unwrap();
+        assert_eq!(twap(start, end), Some((100 + 300 * 5) / 6));
+    }
+
+    #[test]
+    fn twap_survives_accumulator_wrap() {
+        let start = obs(0, u64::MAX - 99, 0);
+        let end = obs(10, start.price_cumulative.wrapping_add(1000), 10);
+        assert_eq!(twap(start, end), Some(100));
+    }
+}
@@ -0,0 +1,215 @@
+// A stand-in that keeps a feed in the Borsh layout `RateData` had before it
+// became zero-copy, used by tests/compute-units.ts as the baseline the tracker's
+// `update_rate` is measured against. Its `update_rate` does what the tracker's
+// did then: Anchor decodes the whole feed, oracle names included, the signer is
+// looked up among the oracles, the median is recomputed and the whole feed is
+// encoded again. The history and TWAP bookkeeping are left out, as their cost
+// doesn't depend on the number of oracles.
+
+// `#[program]` expands to IDL account handlers that still call the deprecated
+// `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+use anchor_lang::prelude::*;
+
+declare_id!("7HNZAR2afAw9NsKi2yzKU1yiWx2UCo3NbKCoTmDdTMv5");
+
+// The tracker's limits at the time.
+pub const MAX_ORACLES: usize = 16;
+pub const MAX_ORACLE_NAME_LEN: usize = 32;
+pub const MAX_CURRENCY_CODE_LEN: usize = 8;
+
+#[program]
+pub mod legacy_bench {
+    use super::*;
+
+    pub fn initialize(ctx: Context<Initialize>, base: String, quote: String) -> Result<()> {
+        require!(
+            base.len() <= MAX_CURRENCY_CODE_LEN && quote.len() <= MAX_CURRENCY_CODE_LEN,
+            ErrorCode::InvalidCurrencyCode
+        );
+        let rate_data = &mut ctx.accounts.rate_data;
+        rate_data.authority = ctx.accounts.authority.key();
+        rate_data.base = base;
+        rate_data.quote = quote;
+        rate_data.bump = ctx.bumps.rate_data;
+        rate_data.exponent = 2;
+        rate_data.aggregation_window_secs = 300;
+        rate_data.max_staleness_secs = 60;
+        Ok(())
+    }
+
+    pub fn add_oracle(ctx: Context<AddOracle>, name: String, oracle_pubkey: Pubkey) -> Result<()> {
+        let rate_data = &mut ctx.accounts.rate_data;
+        require!(name.len() <= MAX_ORACLE_NAME_LEN, ErrorCode::OracleNameTooLong);
+        require!(rate_data.oracles.len() < MAX_ORACLES, ErrorCode::TooManyOracles);
+        rate_data.oracles.push(Oracle { name, pubkey: oracle_pubkey, rate: 0, last_updated: 0 });
+        Ok(())
+    }
+
+    pub fn update_rate(ctx: Context<UpdateRate>, new_rate: u64) -> Result<()> {
+        let rate_data = &mut ctx.accounts.rate_data;
+        let now = Clock::get()?.unix_timestamp;
+        let oracle = rate_data
+            .oracles
+            .iter_mut()
+            .find(|o| o.pubkey == *ctx.accounts.oracle.key)
+            .ok_or(ErrorCode::UnauthorizedOracle)?;
+        oracle.rate = new_rate;
+        oracle.last_updated = now;
+        msg!("Rate updated by {}", oracle.name);
+
+        rate_data.recompute_aggregate(now);
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(base: String, quote: String)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = RateData::space(MAX_ORACLES),
+        seeds = [b"rate_data", base.as_bytes(), b"/", quote.as_bytes()],
+        bump
+    )]
+    pub rate_data: Account<'info, RateData>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddOracle<'info> {
+    #[account(mut, has_one = authority)]
+    pub rate_data: Account<'info, RateData>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRate<'info> {
+    #[account(mut)]
+    pub rate_data: Account<'info, RateData>,
+    pub oracle: Signer<'info>,
+}
+
+// Field for field what `fiat_crypto_tracker::legacy::LegacyRateData` decodes.
+#[account]
+pub struct RateData {
+    pub authority: Pubkey,
+    pub base: String,
+    pub quote: String,
+    pub bump: u8,
+    pub exponent: u8,
+    pub aggregate_rate: u64,
+    pub num_contributors: u8,
+    pub aggregate_timestamp: i64,
+    pub aggregation_window_secs: i64,
+    pub max_staleness_secs: i64,
+    pub price_cumulative: u64,
+    pub live_secs_cumulative: u64,
+    pub cumulative_timestamp: i64,
+    pub oracles: Vec<Oracle>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Oracle {
+    pub name: String,
+    pub pubkey: Pubkey,
+    pub rate: u64,
+    pub last_updated: i64,
+}
+
+impl RateData {
+    // Account size with room for `oracles` oracles, including the discriminator.
+    pub const fn space(oracles: usize) -> usize {
+        8 + 32
+            + 2 * (4 + MAX_CURRENCY_CODE_LEN)
+            + 1 + 1 + 8 + 1 + 8 + 8 + 8 + 8 + 8 + 8
+            + 4 + oracles * (4 + MAX_ORACLE_NAME_LEN + 32 + 8 + 8)
+    }
+
+    // Recomputes the median of every oracle that has published a non-zero rate
+    // within the aggregation window.
+    pub fn recompute_aggregate(&mut self, now: i64) {
+        let window = self.aggregation_window_secs;
+        let mut rates: Vec<u64> = self
+            .oracles
+            .iter()
+            .filter(|o| o.rate != 0 && now.saturating_sub(o.last_updated) <= window)
+            .map(|o| o.rate)
+            .collect();
+
+        self.aggregate_rate = median(&mut rates).unwrap_or(0);
+        self.num_contributors = rates.len() as u8;
+        self.aggregate_timestamp = now;
+    }
+}
+
+// Median of `rates`, averaging the two middle values for an even count.
+fn median(rates: &mut [u64]) -> Option<u64> {
+    if rates.is_empty() {
+        return None;
+    }
+    rates.sort_unstable();
+    let mid = rates.len() / 2;
+    if rates.len() % 2 == 1 {
+        Some(rates[mid])
+    } else {
+        let (lo, hi) = (rates[mid - 1], rates[mid]);
+        Some(lo + (hi - lo) / 2)
+    }
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Currency codes are at most 8 bytes.")]
+    InvalidCurrencyCode,
+    #[msg("The oracle name is longer than 32 bytes.")]
+    OracleNameTooLong,
+    #[msg("The feed already has the maximum number of oracles.")]
+    TooManyOracles,
+    #[msg("The signer is not a registered oracle.")]
+    UnauthorizedOracle,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fiat_crypto_tracker::legacy::LegacyRateData;
+
+    #[test]
+    fn matches_the_legacy_layout() {
+        let pubkey = Pubkey::new_unique();
+        let rate_data = RateData {
+            authority: Pubkey::new_unique(),
+            base: "EUR".to_string(),
+            quote: "NGN".to_string(),
+            bump: 254,
+            exponent: 2,
+            aggregate_rate: 165_000,
+            num_contributors: 1,
+            aggregate_timestamp: 1_000,
+            aggregation_window_secs: 300,
+            max_staleness_secs: 60,
+            price_cumulative: 7,
+            live_secs_cumulative: 3,
+            cumulative_timestamp: 1_000,
+            oracles: vec![Oracle {
+                name: "x".repeat(MAX_ORACLE_NAME_LEN),
+                pubkey,
+                rate: 165_000,
+                last_updated: 1_000,
+            }],
+        };
+        let data = rate_data.try_to_vec().unwrap();
+        assert!(8 + data.len() <= RateData::space(1));
+
+        let legacy = LegacyRateData::deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!((legacy.base.as_str(), legacy.quote.as_str()), ("EUR", "NGN"));
+        assert_eq!(legacy.cumulative_timestamp, 1_000);
+        assert_eq!(legacy.oracles[0].pubkey, pubkey);
+        assert_eq!(legacy.oracles[0].last_updated, 1_000);
+    }
+}
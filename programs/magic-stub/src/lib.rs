@@ -0,0 +1,52 @@
+// A stand-in for the magic program, which only exists on ephemeral rollup
+// validators, used by the tests to exercise `commit_rate_data` and
+// `undelegate_rate_data` on a local validator. It is loaded at the real
+// program's address and takes the same instructions, but only logs each account
+// it is asked to commit instead of scheduling anything.
+
+// `#[program]` expands to IDL account handlers that still call the deprecated
+// `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+use anchor_lang::prelude::*;
+
+declare_id!("Magic11111111111111111111111111111111111111");
+
+#[program]
+pub mod magic_stub {
+    use super::*;
+
+    // The magic program's instructions are bincode-encoded enum variants.
+    #[instruction(discriminator = [1, 0, 0, 0])]
+    pub fn schedule_commit<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ScheduleCommit<'info>>,
+    ) -> Result<()> {
+        log_scheduled("commit", ctx.remaining_accounts)
+    }
+
+    #[instruction(discriminator = [2, 0, 0, 0])]
+    pub fn schedule_commit_and_undelegate<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ScheduleCommit<'info>>,
+    ) -> Result<()> {
+        log_scheduled("commit and undelegation", ctx.remaining_accounts)
+    }
+}
+
+// The accounts to commit follow these as remaining accounts.
+#[derive(Accounts)]
+pub struct ScheduleCommit<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Where the real program queues scheduled commits.
+    #[account(mut)]
+    pub magic_context: UncheckedAccount<'info>,
+}
+
+fn log_scheduled(what: &str, accounts: &[AccountInfo]) -> Result<()> {
+    require!(!accounts.is_empty(), anchor_lang::error::ErrorCode::AccountNotEnoughKeys);
+    for account in accounts {
+        require!(account.is_writable, anchor_lang::error::ErrorCode::ConstraintMut);
+        msg!("Scheduled {} of {}", what, account.key);
+    }
+    Ok(())
+}
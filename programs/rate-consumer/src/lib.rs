@@ -0,0 +1,61 @@
+// A stand-in for a program that consumes the tracker's feeds, used by the tests
+// to exercise the `cpi` helpers and `RateData::load_checked`. Each instruction
+// reads a feed and returns what it read, so the tests can compare it with the
+// tracker's own view.
+
+// `#[program]` expands to IDL account handlers that still call the deprecated
+// `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+use anchor_lang::prelude::*;
+use fiat_crypto_tracker::program::ExchangeRateTracker;
+use fiat_crypto_tracker::checked::CheckedRate;
+use fiat_crypto_tracker::{reader, AggregateQuote, CurrencyPair, OracleRate, RateData, RateQuote};
+
+declare_id!("FBuSgTGWYqJeZHAjKKXeEdVB8rX4fmrGZrJDev4C3tHF");
+
+#[program]
+pub mod rate_consumer {
+    use super::*;
+
+    pub fn read_rate(ctx: Context<ReadFeed>, pair: CurrencyPair) -> Result<RateQuote> {
+        let quote = reader::get_rate(ctx.accounts.tracker(), ctx.accounts.rate_data(), pair)?;
+        msg!("Read rate {} (10^-{})", quote.rate, quote.exponent);
+        Ok(quote)
+    }
+
+    pub fn read_aggregate(ctx: Context<ReadFeed>, pair: CurrencyPair) -> Result<AggregateQuote> {
+        reader::get_aggregate(ctx.accounts.tracker(), ctx.accounts.rate_data(), pair)
+    }
+
+    pub fn read_all_rates(ctx: Context<ReadFeed>, pair: CurrencyPair) -> Result<Vec<OracleRate>> {
+        reader::get_all_rates(ctx.accounts.tracker(), ctx.accounts.rate_data(), pair)
+    }
+
+    // Reads the feed's account directly instead of over CPI.
+    pub fn read_checked_rate(
+        ctx: Context<ReadFeed>,
+        max_age: i64,
+        min_oracles: u8,
+    ) -> Result<CheckedRate> {
+        RateData::load_checked(&ctx.accounts.rate_data(), max_age, min_oracles)
+    }
+}
+
+#[derive(Accounts)]
+pub struct ReadFeed<'info> {
+    /// CHECK: The tracker, or `load_checked`, checks that this is a feed.
+    pub rate_data: UncheckedAccount<'info>,
+    pub tracker_program: Program<'info, ExchangeRateTracker>,
+}
+
+impl<'info> ReadFeed<'info> {
+    fn tracker(&self) -> AccountInfo<'info> {
+        self.tracker_program.to_account_info()
+    }
+
+    fn rate_data(&self) -> AccountInfo<'info> {
+        self.rate_data.to_account_info()
+    }
+}
+
@@ -0,0 +1,133 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { ExchangeRateTracker } from "../target/types/exchange_rate_tracker";
+import { LegacyBench } from "../target/types/legacy_bench";
+import { assert } from "chai";
+import { PublicKey } from "@solana/web3.js";
+
+// Measures the compute units `update_rate` consumes as a feed fills up with oracles,
+// both in the tracker and in programs/legacy-bench, which keeps a feed in the Borsh
+// layout RateData had before it became zero-copy. There every update deserializes
+// and reserializes all oracles and their names, so its cost grows with each oracle
+// added; the zero-copy layout only scans the fixed oracle array, so the cost should
+// stay nearly flat.
+describe("compute units", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.ExchangeRateTracker as Program<ExchangeRateTracker>;
+  const legacy = anchor.workspace.LegacyBench as Program<LegacyBench>;
+  const authority = provider.wallet.publicKey;
+
+  // A pair of its own so the measurements don't depend on the other tests.
+  const eurNgn = { base: "EUR", quote: "NGN" };
+  const findRateDataPDA = (programId: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("rate_data"), Buffer.from(eurNgn.base), Buffer.from("/"), Buffer.from(eurNgn.quote)],
+      programId
+    )[0];
+  const rateDataPDA = findRateDataPDA(program.programId);
+  const legacyRateDataPDA = findRateDataPDA(legacy.programId);
+  const [rateHistoryPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("rate_history"), rateDataPDA.toBuffer()],
+    program.programId
+  );
+
+  // Generous ceiling for a single update; the default per-instruction limit is 200k.
+  const UPDATE_RATE_CU_BUDGET = 60_000;
+  // Extra cost allowed per additional oracle, covering the signer scan and the median.
+  const PER_ORACLE_CU_BUDGET = 1_000;
+  const SAMPLED_COUNTS = [1, 4, 8, 16];
+
+  const unitsConsumed = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    return tx.meta.computeUnitsConsumed;
+  };
+
+  // Adds oracles one at a time and measures an update from the newest one at each
+  // sampled count. The newest oracle sits last, the worst case for the signer scan.
+  const measure = async (
+    addOracle: (name: string, oracle: PublicKey) => Promise<string>,
+    updateRate: (rate: number, oracle: anchor.web3.Keypair) => Promise<string>
+  ) => {
+    const samples: { oracles: number; units: number }[] = [];
+    for (let count = 1; count <= 16; count++) {
+      const oracle = anchor.web3.Keypair.generate();
+      await addOracle(`Oracle ${count}`, oracle.publicKey);
+      if (SAMPLED_COUNTS.includes(count)) {
+        const signature = await updateRate(165000 + count, oracle);
+        samples.push({ oracles: count, units: await unitsConsumed(signature) });
+      }
+    }
+    return samples;
+  };
+
+  const growth = (samples: { oracles: number; units: number }[]) =>
+    samples[samples.length - 1].units - samples[0].units;
+
+  it("Keeps update_rate cost flat as oracles are added", async () => {
+    await program.methods
+      .initialize(eurNgn, 2)
+      .accounts({
+        rateData: rateDataPDA,
+        rateHistory: rateHistoryPDA,
+        authority: authority,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+    await legacy.methods
+      .initialize(eurNgn.base, eurNgn.quote)
+      .accounts({
+        rateData: legacyRateDataPDA,
+        authority: authority,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const zeroCopy = await measure(
+      (name, oracle) =>
+        program.methods
+          .addOracle(eurNgn, name, oracle)
+          .accounts({ rateData: rateDataPDA, authority: authority })
+          .rpc(),
+      (rate, oracle) =>
+        program.methods
+          .updateRate(eurNgn, new anchor.BN(rate))
+          .accounts({ rateData: rateDataPDA, rateHistory: rateHistoryPDA, oracle: oracle.publicKey })
+          .signers([oracle])
+          .rpc({ commitment: "confirmed" })
+    );
+    const borsh = await measure(
+      (name, oracle) =>
+        legacy.methods
+          .addOracle(name, oracle)
+          .accounts({ rateData: legacyRateDataPDA, authority: authority })
+          .rpc(),
+      (rate, oracle) =>
+        legacy.methods
+          .updateRate(new anchor.BN(rate))
+          .accounts({ rateData: legacyRateDataPDA, oracle: oracle.publicKey })
+          .signers([oracle])
+          .rpc({ commitment: "confirmed" })
+    );
+
+    console.table(
+      zeroCopy.map((sample, i) => ({
+        oracles: sample.oracles,
+        "zero-copy": sample.units,
+        "Borsh (legacy)": borsh[i].units,
+      }))
+    );
+
+    for (const { units } of zeroCopy) {
+      assert.isBelow(units, UPDATE_RATE_CU_BUDGET);
+    }
+    const oraclesAdded = SAMPLED_COUNTS[SAMPLED_COUNTS.length - 1] - SAMPLED_COUNTS[0];
+    assert.isBelow(growth(zeroCopy), oraclesAdded * PER_ORACLE_CU_BUDGET);
+    // The legacy layout pays for every oracle on every update; zero-copy doesn't.
+    assert.isAbove(growth(borsh), growth(zeroCopy));
+  });
+});
@@ -1,9 +1,13 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { ExchangeRateTracker } from "../target/types/exchange_rate_tracker";
+import { RateConsumer } from "../target/types/rate_consumer";
+import { DelegationStub } from "../target/types/delegation_stub";
 import { assert } from "chai";
 import { PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createAccount, createMint, getAccount, mintTo } from "@solana/spl-token";
 import bs58 from "bs58";
+import fs from "fs";
 
 describe("exchange-rate-tracker", () => {
   // Configure the client to use the local cluster.
@@ -14,11 +18,29 @@ describe("exchange-rate-tracker", () => {
   const authority = provider.wallet.publicKey;
 
   // --- PDA CALCULATION ---
-  // Find the PDA for the rate_data account. This is now a deterministic address.
-  const [rateDataPDA, _] = PublicKey.findProgramAddressSync(
-    [Buffer.from("rate_data")],
-    program.programId
-  );
+  // Each currency pair has its own rate_data PDA, seeded by its currency codes.
+  const findRateDataPDA = (pair: { base: string; quote: string }) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("rate_data"), Buffer.from(pair.base), Buffer.from("/"), Buffer.from(pair.quote)],
+      program.programId
+    )[0];
+
+  const usdNgn = { base: "USD", quote: "NGN" };
+  const ghsNgn = { base: "GHS", quote: "NGN" };
+  const rateDataPDA = findRateDataPDA(usdNgn);
+
+  // Each pair's price history lives in a companion PDA seeded by its rate_data address.
+  const findRateHistoryPDA = (rateData: PublicKey) =>
+    PublicKey.findProgramAddressSync([Buffer.from("rate_history"), rateData.toBuffer()], program.programId)[0];
+  const rateHistoryPDA = findRateHistoryPDA(rateDataPDA);
+
+  // rate_data is zero-copy: strings are zero-padded byte arrays and only the
+  // first `numOracles` entries of the fixed-size oracle array are in use.
+  const decodeFixed = (bytes: number[]) => Buffer.from(bytes).toString().replace(/\0+$/, "");
+  const activeOracles = (account: { numOracles: number; oracles: any[] }) =>
+    account.oracles.slice(0, account.numOracles);
+  // USD/NGN rates are submitted with two decimals, e.g. 145025 for 1450.25.
+  const usdNgnExponent = 2;
 
   console.log("Your Rate Data PDA is:", rateDataPDA.toBase58());
 
@@ -33,6 +55,44 @@ describe("exchange-rate-tracker", () => {
   console.log("ExchangeRate-API Oracle Pubkey:", exchangeRateApiKeypair.publicKey.toBase58());
   console.log("Binance Oracle Pubkey:", binanceApiKeypair.publicKey.toBase58());
 
+  const sleep = (ms: number) => new Promise(resolve => setTimeout(resolve, ms));
+
+  // Creates a feed with two decimals and registers `oracles` on it.
+  const createFeed = async (
+    pair: { base: string; quote: string },
+    oracles: (readonly [string, anchor.web3.Keypair])[]
+  ) => {
+    const rateData = findRateDataPDA(pair);
+    await program.methods
+      .initialize(pair, 2)
+      .accounts({
+        rateData,
+        rateHistory: findRateHistoryPDA(rateData),
+        authority: authority,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+    for (const [name, oracle] of oracles) {
+      await program.methods
+        .addOracle(pair, name, oracle.publicKey)
+        .accounts({ rateData, authority: authority })
+        .rpc();
+    }
+  };
+
+  // Decodes the Anchor events a confirmed transaction emitted.
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return [...parser.parseLogs(tx.meta.logMessages)];
+  };
+
+  // Keypair the Binance oracle is rotated to
+  const rotatedBinanceKeypair = anchor.web3.Keypair.generate();
+
   // Keypair for an unauthorized account
   const unauthorizedUser = anchor.web3.Keypair.generate();
 
@@ -44,9 +104,10 @@ describe("exchange-rate-tracker", () => {
 
     // Initialize the main rate data account using its PDA
     const tx = await program.methods
-      .initialize()
+      .initialize(usdNgn, usdNgnExponent)
       .accounts({
         rateData: rateDataPDA, // Use the PDA address
+        rateHistory: rateHistoryPDA,
         authority: authority,
         systemProgram: anchor.web3.SystemProgram.programId,
       })
@@ -58,15 +119,126 @@ describe("exchange-rate-tracker", () => {
     // Fetch the created account
     const account = await program.account.rateData.fetch(rateDataPDA);
     
-    // Assert that the authority is set correctly and the oracles list is empty
+    // Assert that the authority and pair are set correctly and the oracles list is empty
     assert.ok(account.authority.equals(authority));
-    assert.isEmpty(account.oracles);
+    assert.equal(decodeFixed(account.base), "USD");
+    assert.equal(decodeFixed(account.quote), "NGN");
+    assert.equal(account.exponent, usdNgnExponent);
+    assert.equal(account.numOracles, 0);
+  });
+
+  it("Initializes a separate feed for another pair", async () => {
+    const ghsNgnPDA = findRateDataPDA(ghsNgn);
+    await program.methods
+      .initialize(ghsNgn, 4)
+      .accounts({
+        rateData: ghsNgnPDA,
+        rateHistory: findRateHistoryPDA(ghsNgnPDA),
+        authority: authority,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .addOracle(ghsNgn, "ExchangeRate-API", exchangeRateApiKeypair.publicKey)
+      .accounts({
+        rateData: ghsNgnPDA,
+        authority: authority,
+      })
+      .rpc();
+
+    const account = await program.account.rateData.fetch(ghsNgnPDA);
+    assert.equal(decodeFixed(account.base), "GHS");
+    assert.equal(decodeFixed(account.quote), "NGN");
+    assert.equal(account.exponent, 4);
+    assert.equal(account.numOracles, 1);
+
+    // The USD/NGN feed is untouched.
+    const usdAccount = await program.account.rateData.fetch(rateDataPDA);
+    assert.equal(usdAccount.numOracles, 0);
+  });
+
+  it("Caps a feed at MAX_ORACLES", async () => {
+    const ghsNgnPDA = findRateDataPDA(ghsNgn);
+    const addOracle = (name: string, pubkey: PublicKey) =>
+      program.methods
+        .addOracle(ghsNgn, name, pubkey)
+        .accounts({
+          rateData: ghsNgnPDA,
+          authority: authority,
+        })
+        .rpc();
+
+    // Fill the feed up to MAX_ORACLES (16).
+    for (let i = 2; i <= 16; i++) {
+      await addOracle(`Oracle ${i}`, anchor.web3.Keypair.generate().publicKey);
+    }
+    const account = await program.account.rateData.fetch(ghsNgnPDA);
+    assert.equal(account.numOracles, 16);
+
+    try {
+      await addOracle("Oracle 17", anchor.web3.Keypair.generate().publicKey);
+      assert.fail("Should have failed past the oracle cap.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "TooManyOracles");
+    }
+  });
+
+  it("Rejects oracle names longer than 32 bytes", async () => {
+    try {
+      await program.methods
+        .addOracle(usdNgn, "A".repeat(33), anchor.web3.Keypair.generate().publicKey)
+        .accounts({
+          rateData: rateDataPDA,
+          authority: authority,
+        })
+        .rpc();
+      assert.fail("Should have failed for a long name.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "OracleNameTooLong");
+    }
+  });
+
+  it("Rejects an exponent that cannot be represented", async () => {
+    const kesNgn = { base: "KES", quote: "NGN" };
+    try {
+      await program.methods
+        .initialize(kesNgn, 19)
+        .accounts({
+          rateData: findRateDataPDA(kesNgn),
+          rateHistory: findRateHistoryPDA(findRateDataPDA(kesNgn)),
+          authority: authority,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("Should have failed for an exponent above 18.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidExponent");
+    }
+  });
+
+  it("Rejects invalid currency codes", async () => {
+    const badPair = { base: "usd", quote: "NGN" };
+    try {
+      await program.methods
+        .initialize(badPair, 2)
+        .accounts({
+          rateData: findRateDataPDA(badPair),
+          rateHistory: findRateHistoryPDA(findRateDataPDA(badPair)),
+          authority: authority,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("Should have failed for a lowercase currency code.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidCurrencyCode");
+    }
   });
 
   it("Adds the ExchangeRate-API and Binance oracles", async () => {
     // Add the first oracle
     await program.methods
-      .addOracle("ExchangeRate-API", exchangeRateApiKeypair.publicKey)
+      .addOracle(usdNgn, "ExchangeRate-API", exchangeRateApiKeypair.publicKey)
       .accounts({
         rateData: rateDataPDA,
         authority: authority,
@@ -75,7 +247,7 @@ describe("exchange-rate-tracker", () => {
 
     // Add the second oracle
     await program.methods
-      .addOracle("Binance", binanceApiKeypair.publicKey)
+      .addOracle(usdNgn, "Binance", binanceApiKeypair.publicKey)
       .accounts({
         rateData: rateDataPDA,
         authority: authority,
@@ -86,65 +258,232 @@ describe("exchange-rate-tracker", () => {
     const account = await program.account.rateData.fetch(rateDataPDA);
     
     // Assert that there are now two oracles in the list
-    assert.lengthOf(account.oracles, 2);
+    assert.equal(account.numOracles, 2);
     
     // Verify the details of the first oracle
-    const firstOracle = account.oracles.find(o => o.name === "ExchangeRate-API");
+    const firstOracle = activeOracles(account).find(o => decodeFixed(o.name) === "ExchangeRate-API");
     assert.isDefined(firstOracle);
     assert.ok(firstOracle.pubkey.equals(exchangeRateApiKeypair.publicKey));
 
     // Verify the details of the second oracle
-    const secondOracle = account.oracles.find(o => o.name === "Binance");
+    const secondOracle = activeOracles(account).find(o => decodeFixed(o.name) === "Binance");
     assert.isDefined(secondOracle);
     assert.ok(secondOracle.pubkey.equals(binanceApiKeypair.publicKey));
   });
 
 
   it("Allows the ExchangeRate-API oracle to update the rate", async () => {
-    const newRate = new anchor.BN(1450);
+    const newRate = new anchor.BN(145000);
     await program.methods
-      .updateRate(newRate)
+      .updateRate(usdNgn, newRate)
       .accounts({
         rateData: rateDataPDA,
+        rateHistory: rateHistoryPDA,
         oracle: exchangeRateApiKeypair.publicKey,
       })
       .signers([exchangeRateApiKeypair]) // The oracle must sign the transaction
       .rpc();
 
     const account = await program.account.rateData.fetch(rateDataPDA);
-    const updatedOracle = account.oracles.find(o => o.pubkey.equals(exchangeRateApiKeypair.publicKey));
+    const updatedOracle = activeOracles(account).find(o => o.pubkey.equals(exchangeRateApiKeypair.publicKey));
 
     assert.isDefined(updatedOracle);
     assert.equal(updatedOracle.rate.toNumber(), newRate.toNumber());
   });
 
   it("Allows the Binance oracle to update the rate", async () => {
-    const newRate = new anchor.BN(1510);
+    const newRate = new anchor.BN(151000);
     await program.methods
-      .updateRate(newRate)
+      .updateRate(usdNgn, newRate)
       .accounts({
         rateData: rateDataPDA,
+        rateHistory: rateHistoryPDA,
         oracle: binanceApiKeypair.publicKey,
       })
       .signers([binanceApiKeypair]) // The oracle must sign the transaction
       .rpc();
 
     const account = await program.account.rateData.fetch(rateDataPDA);
-    const updatedOracle = account.oracles.find(o => o.pubkey.equals(binanceApiKeypair.publicKey));
+    const updatedOracle = activeOracles(account).find(o => o.pubkey.equals(binanceApiKeypair.publicKey));
 
     assert.isDefined(updatedOracle);
     assert.equal(updatedOracle.rate.toNumber(), newRate.toNumber());
   });
 
+  it("Aggregates the median of both fresh oracles", async () => {
+    const account = await program.account.rateData.fetch(rateDataPDA);
+
+    // Median of 1450.00 and 1510.00 at exponent 2.
+    assert.equal(account.aggregateRate.toNumber(), 148000);
+    assert.equal(account.numContributors, 2);
+    assert.isAbove(account.aggregateTimestamp.toNumber(), 0);
+  });
+
+  it("Rejects a non-positive aggregation window", async () => {
+    try {
+      await program.methods
+        .setAggregationWindow(usdNgn, new anchor.BN(0))
+        .accounts({
+          rateData: rateDataPDA,
+          authority: authority,
+        })
+        .rpc();
+      assert.fail("Should have failed for a zero window.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidAggregationWindow");
+    }
+  });
+
+  it("Records both updates in the price history", async () => {
+    const history = await program.account.rateHistory.fetch(rateHistoryPDA);
+    assert.equal(history.capacity, 128);
+    assert.equal(history.nextSeq.toNumber(), 2);
+
+    const range = await program.methods
+      .getHistory(usdNgn, new anchor.BN(0), 10)
+      .accounts({ rateData: rateDataPDA, rateHistory: rateHistoryPDA })
+      .view();
+
+    assert.equal(range.firstSeq.toNumber(), 0);
+    assert.deepEqual(range.entries.map(e => e.rate.toNumber()), [145000, 151000]);
+    assert.deepEqual(
+      range.entries.map(e => e.oracle.toBase58()),
+      [exchangeRateApiKeypair.publicKey.toBase58(), binanceApiKeypair.publicKey.toBase58()]
+    );
+  });
+
+  it("Returns a TWAP over a window the history covers", async () => {
+    await sleep(2000);
+    const quote = await program.methods
+      .getTwap(usdNgn, new anchor.BN(1))
+      .accounts({ rateData: rateDataPDA, rateHistory: rateHistoryPDA })
+      .view();
+
+    // The aggregate has been 1480.00 for at least the last second.
+    assert.equal(quote.twap.toNumber(), 148000);
+    assert.equal(quote.exponent, usdNgnExponent);
+    assert.equal(quote.windowSecs.toNumber(), 1);
+  });
+
+  it("Rejects a TWAP window longer than the retained history", async () => {
+    try {
+      await program.methods
+        .getTwap(usdNgn, new anchor.BN(24 * 60 * 60))
+        .accounts({ rateData: rateDataPDA, rateHistory: rateHistoryPDA })
+        .rpc();
+      assert.fail("Should have failed for a window the history does not cover.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "TwapWindowTooLong");
+    }
+  });
+
+  it("Keeps the most recent entries when the history is shrunk", async () => {
+    const resize = (capacity: number) =>
+      program.methods
+        .resizeHistory(usdNgn, capacity)
+        .accounts({
+          rateData: rateDataPDA,
+          rateHistory: rateHistoryPDA,
+          authority: authority,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+    await resize(1);
+    let history = await program.account.rateHistory.fetch(rateHistoryPDA);
+    assert.equal(history.capacity, 1);
+    assert.deepEqual(history.entries.map(e => e.rate.toNumber()), [151000]);
+
+    // Sequence numbers survive the resize, so older entries are skipped.
+    const range = await program.methods
+      .getHistory(usdNgn, new anchor.BN(0), 10)
+      .accounts({ rateData: rateDataPDA, rateHistory: rateHistoryPDA })
+      .view();
+    assert.equal(range.firstSeq.toNumber(), 1);
+
+    await resize(128);
+    history = await program.account.rateHistory.fetch(rateHistoryPDA);
+    assert.equal(history.capacity, 128);
+    assert.lengthOf(history.entries, 1);
+  });
+
+  it("Prevents anyone but the authority from resizing the history", async () => {
+    try {
+      await program.methods
+        .resizeHistory(usdNgn, 4)
+        .accounts({
+          rateData: rateDataPDA,
+          rateHistory: rateHistoryPDA,
+          authority: unauthorizedUser.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([unauthorizedUser])
+        .rpc();
+      assert.fail("Should have failed for a non-authority signer.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "ConstraintHasOne");
+    }
+  });
+
+  it("Returns the fresh aggregate from get_rate", async () => {
+    const quote = await program.methods
+      .getRate(usdNgn)
+      .accounts({ rateData: rateDataPDA })
+      .view();
+
+    assert.equal(quote.rate.toNumber(), 148000);
+    assert.equal(quote.exponent, usdNgnExponent);
+    assert.isAbove(quote.timestamp.toNumber(), 0);
+  });
+
+  it("Fails get_rate once the aggregate is older than the max staleness", async () => {
+    const setMaxStaleness = (secs: number) =>
+      program.methods
+        .setMaxStaleness(usdNgn, new anchor.BN(secs))
+        .accounts({
+          rateData: rateDataPDA,
+          authority: authority,
+        })
+        .rpc();
+
+    const assertStale = async () => {
+      try {
+        await program.methods
+          .getRate(usdNgn)
+          .accounts({ rateData: rateDataPDA })
+          .rpc();
+        assert.fail("Should have failed for a stale rate.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "StaleRate");
+      }
+    };
+
+    await setMaxStaleness(1);
+    await sleep(3000);
+
+    try {
+      await assertStale();
+      // Admin changes recompute the aggregate without making its rates any newer.
+      await program.methods
+        .setOracleWeight(usdNgn, exchangeRateApiKeypair.publicKey, 1)
+        .accounts({ rateData: rateDataPDA, authority: authority })
+        .rpc();
+      await assertStale();
+    } finally {
+      await setMaxStaleness(60);
+    }
+  });
+
 
   it("Prevents an unauthorized user from updating the rate", async () => {
     try {
-      const newRate = new anchor.BN(1500);
+      const newRate = new anchor.BN(150000);
       // Attempt to update the rate with an unauthorized signer
       await program.methods
-        .updateRate(newRate)
+        .updateRate(usdNgn, newRate)
         .accounts({
           rateData: rateDataPDA,
+          rateHistory: rateHistoryPDA,
           oracle: unauthorizedUser.publicKey,
         })
         .signers([unauthorizedUser])
@@ -156,4 +495,1698 @@ describe("exchange-rate-tracker", () => {
       assert.equal(err.error.errorMessage, "The provided oracle is not authorized to update rates.");
     }
   });
+
+  it("Replaces the Binance oracle key and keeps its history", async () => {
+    const before = await program.account.rateData.fetch(rateDataPDA);
+    const oldOracle = activeOracles(before).find(o => o.pubkey.equals(binanceApiKeypair.publicKey));
+
+    await program.methods
+      .replaceOracleKey(usdNgn, binanceApiKeypair.publicKey, rotatedBinanceKeypair.publicKey)
+      .accounts({
+        rateData: rateDataPDA,
+        authority: authority,
+      })
+      .rpc();
+
+    const account = await program.account.rateData.fetch(rateDataPDA);
+    assert.isUndefined(activeOracles(account).find(o => o.pubkey.equals(binanceApiKeypair.publicKey)));
+
+    const rotated = activeOracles(account).find(o => o.pubkey.equals(rotatedBinanceKeypair.publicKey));
+    assert.isDefined(rotated);
+    assert.equal(decodeFixed(rotated.name), decodeFixed(oldOracle.name));
+    assert.equal(rotated.rate.toNumber(), oldOracle.rate.toNumber());
+    assert.equal(rotated.lastUpdated.toNumber(), oldOracle.lastUpdated.toNumber());
+  });
+
+  it("Prevents replacing a key that is not registered", async () => {
+    try {
+      await program.methods
+        .replaceOracleKey(usdNgn, unauthorizedUser.publicKey, anchor.web3.Keypair.generate().publicKey)
+        .accounts({
+          rateData: rateDataPDA,
+          authority: authority,
+        })
+        .rpc();
+      assert.fail("Should have failed for an unknown oracle.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "OracleNotFound");
+    }
+  });
+
+  it("Removes the ExchangeRate-API oracle", async () => {
+    await program.methods
+      .removeOracle(usdNgn, exchangeRateApiKeypair.publicKey)
+      .accounts({
+        rateData: rateDataPDA,
+        authority: authority,
+      })
+      .rpc();
+
+    const account = await program.account.rateData.fetch(rateDataPDA);
+    assert.equal(account.numOracles, 1);
+    assert.isUndefined(activeOracles(account).find(o => o.pubkey.equals(exchangeRateApiKeypair.publicKey)));
+
+    // Only the rotated Binance oracle is left in the aggregate.
+    assert.equal(account.aggregateRate.toNumber(), 151000);
+    assert.equal(account.numContributors, 1);
+  });
+
+  it("Prevents a removed oracle from updating the rate", async () => {
+    try {
+      await program.methods
+        .updateRate(usdNgn, new anchor.BN(146000))
+        .accounts({
+          rateData: rateDataPDA,
+          rateHistory: rateHistoryPDA,
+          oracle: exchangeRateApiKeypair.publicKey,
+        })
+        .signers([exchangeRateApiKeypair])
+        .rpc();
+      assert.fail("Should have failed for a removed oracle.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "UnauthorizedOracle");
+    }
+  });
+
+  it("Prevents removing an oracle that is not registered", async () => {
+    try {
+      await program.methods
+        .removeOracle(usdNgn, exchangeRateApiKeypair.publicKey)
+        .accounts({
+          rateData: rateDataPDA,
+          authority: authority,
+        })
+        .rpc();
+      assert.fail("Should have failed for an unknown oracle.");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "OracleNotFound");
+    }
+  });
+
+  describe("transferring a feed's authority", () => {
+    const ghsNgnPDA = findRateDataPDA(ghsNgn);
+    const newAuthority = anchor.web3.Keypair.generate();
+
+    const propose = (pending: PublicKey, currentAuthority?: anchor.web3.Keypair) =>
+      program.methods
+        .proposeAuthority(ghsNgn, pending)
+        .accounts({
+          rateData: ghsNgnPDA,
+          authority: currentAuthority ? currentAuthority.publicKey : authority,
+        })
+        .signers(currentAuthority ? [currentAuthority] : [])
+        .rpc({ commitment: "confirmed" });
+
+    const accept = (signer?: anchor.web3.Keypair) =>
+      program.methods
+        .acceptAuthority(ghsNgn)
+        .accounts({
+          rateData: ghsNgnPDA,
+          newAuthority: signer ? signer.publicKey : authority,
+        })
+        .signers(signer ? [signer] : [])
+        .rpc({ commitment: "confirmed" });
+
+    const cancel = () =>
+      program.methods
+        .cancelAuthorityTransfer(ghsNgn)
+        .accounts({
+          rateData: ghsNgnPDA,
+          authority: authority,
+        })
+        .rpc({ commitment: "confirmed" });
+
+    it("Proposes a new authority without handing over control", async () => {
+      const signature = await propose(newAuthority.publicKey);
+
+      const account = await program.account.rateData.fetch(ghsNgnPDA);
+      assert.ok(account.authority.equals(authority));
+      assert.ok(account.pendingAuthority.equals(newAuthority.publicKey));
+
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "authorityTransferProposed");
+      assert.ok(event.data.pendingAuthority.equals(newAuthority.publicKey));
+    });
+
+    it("Only lets the proposed key accept", async () => {
+      try {
+        await accept(unauthorizedUser);
+        assert.fail("Should have failed for a key that was not proposed.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "NotPendingAuthority");
+      }
+    });
+
+    it("Cancels a pending transfer", async () => {
+      const signature = await cancel();
+
+      const account = await program.account.rateData.fetch(ghsNgnPDA);
+      assert.ok(account.pendingAuthority.equals(PublicKey.default));
+
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "authorityTransferCancelled");
+      assert.ok(event.data.cancelledAuthority.equals(newAuthority.publicKey));
+
+      try {
+        await cancel();
+        assert.fail("Should have failed with nothing to cancel.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "NoPendingAuthority");
+      }
+    });
+
+    it("Hands the feed over once the proposed key accepts", async () => {
+      await propose(newAuthority.publicKey);
+      const signature = await accept(newAuthority);
+
+      const account = await program.account.rateData.fetch(ghsNgnPDA);
+      assert.ok(account.authority.equals(newAuthority.publicKey));
+      assert.ok(account.pendingAuthority.equals(PublicKey.default));
+
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "authorityTransferAccepted");
+      assert.ok(event.data.oldAuthority.equals(authority));
+      assert.ok(event.data.newAuthority.equals(newAuthority.publicKey));
+
+      // The previous authority can no longer manage the feed.
+      try {
+        await propose(unauthorizedUser.publicKey);
+        assert.fail("Should have failed for the previous authority.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ConstraintHasOne");
+      }
+
+      // Hand it back so the wallet stays in control of every test feed.
+      await propose(authority, newAuthority);
+      await accept();
+    });
+  });
+
+  describe("governing a feed with an admin council", () => {
+    const kesNgn = { base: "KES", quote: "NGN" };
+    const kesNgnPDA = findRateDataPDA(kesNgn);
+    const members = [0, 1, 2].map(() => anchor.web3.Keypair.generate());
+    const councilOracle = anchor.web3.Keypair.generate();
+
+    const findProposalPDA = (id: number) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("admin_proposal"), kesNgnPDA.toBuffer(), new anchor.BN(id).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const propose = (member: anchor.web3.Keypair, id: number, action: any) =>
+      program.methods
+        .proposeAdminAction(kesNgn, new anchor.BN(id), action)
+        .accounts({
+          rateData: kesNgnPDA,
+          proposal: findProposalPDA(id),
+          proposer: member.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([member])
+        .rpc({ commitment: "confirmed" });
+
+    const approve = (member: anchor.web3.Keypair, id: number) =>
+      program.methods
+        .approveAdminAction(kesNgn, new anchor.BN(id))
+        .accounts({
+          rateData: kesNgnPDA,
+          proposal: findProposalPDA(id),
+          member: member.publicKey,
+        })
+        .signers([member])
+        .rpc({ commitment: "confirmed" });
+
+    const execute = (id: number, proposer: PublicKey) =>
+      program.methods
+        .executeAdminAction(kesNgn, new anchor.BN(id))
+        .accounts({
+          rateData: kesNgnPDA,
+          proposal: findProposalPDA(id),
+          proposer,
+        })
+        .rpc({ commitment: "confirmed" });
+
+    const addOracleDirectly = (name: string, pubkey: PublicKey) =>
+      program.methods
+        .addOracle(kesNgn, name, pubkey)
+        .accounts({
+          rateData: kesNgnPDA,
+          authority: authority,
+        })
+        .rpc();
+
+    before(async () => {
+      await program.methods
+        .initialize(kesNgn, 2)
+        .accounts({
+          rateData: kesNgnPDA,
+          rateHistory: findRateHistoryPDA(kesNgnPDA),
+          authority: authority,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+      // Proposers pay for their proposal accounts.
+      for (const member of members) {
+        const sig = await provider.connection.requestAirdrop(member.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(sig);
+      }
+    });
+
+    it("Rejects a council whose threshold cannot be reached", async () => {
+      try {
+        await program.methods
+          .setCouncil(kesNgn, members.map(m => m.publicKey), 4)
+          .accounts({ rateData: kesNgnPDA, authority: authority })
+          .rpc();
+        assert.fail("Should have failed for a threshold above the member count.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "InvalidCouncil");
+      }
+    });
+
+    it("Hands admin changes to a 2-of-3 council", async () => {
+      await program.methods
+        .setCouncil(kesNgn, members.map(m => m.publicKey), 2)
+        .accounts({ rateData: kesNgnPDA, authority: authority })
+        .rpc();
+
+      const account = await program.account.rateData.fetch(kesNgnPDA);
+      assert.equal(account.councilSize, 3);
+      assert.equal(account.councilThreshold, 2);
+
+      try {
+        await addOracleDirectly("Bank K", councilOracle.publicKey);
+        assert.fail("Should have failed once the feed has a council.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "CouncilApprovalRequired");
+      }
+    });
+
+    it("Only lets council members propose, with the next proposal id", async () => {
+      const action = { addOracle: { name: "Bank K", pubkey: councilOracle.publicKey } };
+      try {
+        await propose(unauthorizedUser, 0, action);
+        assert.fail("Should have failed for a non-member.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "NotCouncilMember");
+      }
+      try {
+        await propose(members[0], 1, action);
+        assert.fail("Should have failed for a skipped proposal id.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "InvalidProposalId");
+      }
+    });
+
+    it("Executes a proposal once the threshold is met", async () => {
+      const action = { addOracle: { name: "Bank K", pubkey: councilOracle.publicKey } };
+      await propose(members[0], 0, action);
+
+      try {
+        await execute(0, members[0].publicKey);
+        assert.fail("Should have failed with only the proposer's approval.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "NotEnoughApprovals");
+      }
+      try {
+        await approve(members[0], 0);
+        assert.fail("Should have failed for a repeated approval.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "AlreadyApproved");
+      }
+
+      const approveSig = await approve(members[1], 0);
+      const [approved] = await eventsOf(approveSig);
+      assert.equal(approved.name, "adminActionApproved");
+      assert.equal(approved.data.approvals, 2);
+
+      const executeSig = await execute(0, members[0].publicKey);
+      // The action reports itself before the proposal is marked executed.
+      const [added, executed] = await eventsOf(executeSig);
+      assert.equal(added.name, "oracleAdded");
+      assert.equal(executed.name, "adminActionExecuted");
+
+      const account = await program.account.rateData.fetch(kesNgnPDA);
+      assert.deepEqual(activeOracles(account).map(o => decodeFixed(o.name)), ["Bank K"]);
+      assert.equal(account.nextProposalId.toNumber(), 1);
+
+      // The executed proposal is closed and its rent returned to the proposer.
+      assert.isNull(await provider.connection.getAccountInfo(findProposalPDA(0)));
+    });
+
+    it("Refuses to resize the history behind the council's back", async () => {
+      try {
+        await program.methods
+          .resizeHistory(kesNgn, 4)
+          .accounts({
+            rateData: kesNgnPDA,
+            rateHistory: findRateHistoryPDA(kesNgnPDA),
+            authority: authority,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .rpc();
+        assert.fail("Should have failed once the feed has a council.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "CouncilApprovalRequired");
+      }
+    });
+
+    it("Withdraws a pending authority transfer through a proposal", async () => {
+      const pending = anchor.web3.Keypair.generate().publicKey;
+      await propose(members[0], 1, { proposeAuthority: { newAuthority: pending } });
+      await approve(members[1], 1);
+      await execute(1, members[0].publicKey);
+      let account = await program.account.rateData.fetch(kesNgnPDA);
+      assert.ok(account.pendingAuthority.equals(pending));
+
+      try {
+        await program.methods
+          .cancelAuthorityTransfer(kesNgn)
+          .accounts({ rateData: kesNgnPDA, authority: authority })
+          .rpc();
+        assert.fail("Should have failed once the feed has a council.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "CouncilApprovalRequired");
+      }
+
+      await propose(members[1], 2, { cancelAuthorityTransfer: {} });
+      await approve(members[2], 2);
+      const signature = await execute(2, members[1].publicKey);
+      account = await program.account.rateData.fetch(kesNgnPDA);
+      assert.ok(account.pendingAuthority.equals(PublicKey.default));
+      const [cancelled] = await eventsOf(signature);
+      assert.equal(cancelled.name, "authorityTransferCancelled");
+      assert.ok(cancelled.data.cancelledAuthority.equals(pending));
+    });
+
+    it("Dissolves the council through a proposal", async () => {
+      await propose(members[2], 3, { setCouncil: { members: [], threshold: 0 } });
+      await approve(members[1], 3);
+      await execute(3, members[2].publicKey);
+
+      const account = await program.account.rateData.fetch(kesNgnPDA);
+      assert.equal(account.councilSize, 0);
+
+      // The authority manages the feed directly again.
+      await addOracleDirectly("Bank L", anchor.web3.Keypair.generate().publicKey);
+    });
+  });
+
+  describe("pausing a feed and halting oracles", () => {
+    const xofNgn = { base: "XOF", quote: "NGN" };
+    const xofNgnPDA = findRateDataPDA(xofNgn);
+    const xofNgnHistoryPDA = findRateHistoryPDA(xofNgnPDA);
+    const bankOracle = anchor.web3.Keypair.generate();
+    const marketOracle = anchor.web3.Keypair.generate();
+
+    const updateRate = (oracle: anchor.web3.Keypair, rate: number) =>
+      program.methods
+        .updateRate(xofNgn, new anchor.BN(rate))
+        .accounts({
+          rateData: xofNgnPDA,
+          rateHistory: xofNgnHistoryPDA,
+          oracle: oracle.publicKey,
+        })
+        .signers([oracle])
+        .rpc();
+
+    const getRate = () => program.methods.getRate(xofNgn).accounts({ rateData: xofNgnPDA }).rpc();
+
+    const setHalted = (oracle: PublicKey, halted: boolean) =>
+      program.methods
+        .setOracleHalted(xofNgn, oracle, halted)
+        .accounts({ rateData: xofNgnPDA, authority: authority })
+        .rpc();
+
+    before(async () => {
+      await program.methods
+        .initialize(xofNgn, 2)
+        .accounts({
+          rateData: xofNgnPDA,
+          rateHistory: xofNgnHistoryPDA,
+          authority: authority,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      for (const [name, oracle] of [["Bank X", bankOracle], ["Market X", marketOracle]] as const) {
+        await program.methods
+          .addOracle(xofNgn, name, oracle.publicKey)
+          .accounts({ rateData: xofNgnPDA, authority: authority })
+          .rpc();
+      }
+      await updateRate(bankOracle, 250);
+      await updateRate(marketOracle, 270);
+    });
+
+    it("Drops a halted oracle from the aggregate and rejects its updates", async () => {
+      await setHalted(bankOracle.publicKey, true);
+
+      let account = await program.account.rateData.fetch(xofNgnPDA);
+      assert.equal(account.aggregateRate.toNumber(), 270);
+      assert.equal(account.numContributors, 1);
+
+      try {
+        await updateRate(bankOracle, 255);
+        assert.fail("Should have failed for a halted oracle.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "OracleHalted");
+      }
+
+      await setHalted(bankOracle.publicKey, false);
+      account = await program.account.rateData.fetch(xofNgnPDA);
+      assert.equal(account.aggregateRate.toNumber(), 260);
+    });
+
+    it("Rejects updates and reads while the feed is paused", async () => {
+      await program.methods
+        .pause(xofNgn)
+        .accounts({ rateData: xofNgnPDA, authority: authority })
+        .rpc();
+
+      const account = await program.account.rateData.fetch(xofNgnPDA);
+      assert.equal(account.status, 1);
+
+      try {
+        await updateRate(marketOracle, 275);
+        assert.fail("Should have failed while paused.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "FeedPaused");
+      }
+      try {
+        await getRate();
+        assert.fail("Should have failed while paused.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "FeedPaused");
+      }
+    });
+
+    it("Only lets the authority pause or unpause", async () => {
+      try {
+        await program.methods
+          .unpause(xofNgn)
+          .accounts({ rateData: xofNgnPDA, authority: unauthorizedUser.publicKey })
+          .signers([unauthorizedUser])
+          .rpc();
+        assert.fail("Should have failed for a non-authority signer.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ConstraintHasOne");
+      }
+    });
+
+    it("Resumes updates and reads once unpaused", async () => {
+      await program.methods
+        .unpause(xofNgn)
+        .accounts({ rateData: xofNgnPDA, authority: authority })
+        .rpc();
+
+      await updateRate(marketOracle, 280);
+      await getRate();
+      const account = await program.account.rateData.fetch(xofNgnPDA);
+      assert.equal(account.status, 0);
+      assert.equal(account.aggregateRate.toNumber(), 265);
+    });
+
+    it("Keeps the TWAP exact across a halt between updates", async () => {
+      // The halt moves the aggregate to 280 without an update; the next update
+      // comes 3s later, so the window below starts between the two.
+      await setHalted(bankOracle.publicKey, true);
+      await sleep(3000);
+      await updateRate(marketOracle, 280);
+      await sleep(1000);
+
+      const quote = await program.methods
+        .getTwap(xofNgn, new anchor.BN(2))
+        .accounts({ rateData: xofNgnPDA, rateHistory: xofNgnHistoryPDA })
+        .view();
+      assert.equal(quote.twap.toNumber(), 280);
+
+      await setHalted(bankOracle.publicKey, false);
+    });
+  });
+
+  describe("rejecting outlier rates", () => {
+    const egpNgn = { base: "EGP", quote: "NGN" };
+    const egpNgnPDA = findRateDataPDA(egpNgn);
+    const egpNgnHistoryPDA = findRateHistoryPDA(egpNgnPDA);
+    const firstOracle = anchor.web3.Keypair.generate();
+    const secondOracle = anchor.web3.Keypair.generate();
+
+    const updateRate = (oracle: anchor.web3.Keypair, rate: anchor.BN) =>
+      program.methods
+        .updateRate(egpNgn, rate)
+        .accounts({
+          rateData: egpNgnPDA,
+          rateHistory: egpNgnHistoryPDA,
+          oracle: oracle.publicKey,
+        })
+        .signers([oracle])
+        .rpc();
+
+    before(async () => {
+      await program.methods
+        .initialize(egpNgn, 2)
+        .accounts({
+          rateData: egpNgnPDA,
+          rateHistory: egpNgnHistoryPDA,
+          authority: authority,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      for (const [name, oracle] of [["First", firstOracle], ["Second", secondOracle]] as const) {
+        await program.methods
+          .addOracle(egpNgn, name, oracle.publicKey)
+          .accounts({ rateData: egpNgnPDA, authority: authority })
+          .rpc();
+      }
+      // Allow updates within 5% of the aggregate.
+      await program.methods
+        .setMaxDeviation(egpNgn, 500)
+        .accounts({ rateData: egpNgnPDA, authority: authority })
+        .rpc();
+    });
+
+    it("Accepts the feed's first rate and updates within the band", async () => {
+      await updateRate(firstOracle, new anchor.BN(10000));
+      await updateRate(secondOracle, new anchor.BN(10400));
+
+      const account = await program.account.rateData.fetch(egpNgnPDA);
+      assert.equal(account.maxDeviationBps, 500);
+      assert.equal(account.aggregateRate.toNumber(), 10200);
+    });
+
+    it("Rejects updates outside the band", async () => {
+      for (const rate of [new anchor.BN(12000), new anchor.BN(1), new anchor.BN("18446744073709551615")]) {
+        try {
+          await updateRate(secondOracle, rate);
+          assert.fail("Should have failed for an outlier rate.");
+        } catch (err) {
+          assert.equal(err.error.errorCode.code, "RateDeviationTooLarge");
+        }
+      }
+
+      const account = await program.account.rateData.fetch(egpNgnPDA);
+      assert.equal(account.aggregateRate.toNumber(), 10200);
+    });
+
+    it("Counts outliers against the oracle when the policy is to record them", async () => {
+      await program.methods
+        .setOutlierPolicy(egpNgn, { record: {} })
+        .accounts({ rateData: egpNgnPDA, authority: authority })
+        .rpc();
+
+      // The update succeeds but the rate is dropped.
+      await updateRate(secondOracle, new anchor.BN(12000));
+      const account = await program.account.rateData.fetch(egpNgnPDA);
+      const [, second] = activeOracles(account);
+      assert.equal(second.rate.toNumber(), 10400);
+      assert.equal(second.rejectedCount.toNumber(), 1);
+      assert.equal(account.aggregateRate.toNumber(), 10200);
+    });
+
+    it("Ranks oracles by their distance from consensus", async () => {
+      // Exactly on the aggregate.
+      await updateRate(firstOracle, new anchor.BN(10200));
+
+      const table = await program.methods
+        .getReputation(egpNgn)
+        .accounts({ rateData: egpNgnPDA })
+        .view();
+      assert.deepEqual(
+        table.map(r => [r.pubkey.toBase58(), r.updateCount.toNumber(), r.rejectedCount.toNumber()]),
+        [
+          [firstOracle.publicKey.toBase58(), 2, 0],
+          [secondOracle.publicKey.toBase58(), 1, 1],
+        ]
+      );
+      // 10400 against an aggregate of 10000.
+      assert.equal(table[1].meanAbsDeviationBps.toNumber(), 400);
+      assert.equal(table[0].meanAbsDeviationBps.toNumber(), 0);
+    });
+
+    it("Derives aggregation weights from reputation", async () => {
+      await program.methods
+        .applyReputationWeights(egpNgn)
+        .accounts({ rateData: egpNgnPDA, authority: authority })
+        .rpc();
+
+      const account = await program.account.rateData.fetch(egpNgnPDA);
+      // 100 / (1 + 400 / 100), halved again for one rejection in two submissions.
+      assert.deepEqual(activeOracles(account).map(o => o.weight), [100, 10]);
+    });
+  });
+
+  describe("rate limiting oracle updates", () => {
+    const tzsNgn = { base: "TZS", quote: "NGN" };
+    const tzsNgnPDA = findRateDataPDA(tzsNgn);
+    const fastOracle = anchor.web3.Keypair.generate();
+    const slowOracle = anchor.web3.Keypair.generate();
+
+    const updateRate = (oracle: anchor.web3.Keypair, rate: number) =>
+      program.methods
+        .updateRate(tzsNgn, new anchor.BN(rate))
+        .accounts({
+          rateData: tzsNgnPDA,
+          rateHistory: findRateHistoryPDA(tzsNgnPDA),
+          oracle: oracle.publicKey,
+        })
+        .signers([oracle])
+        .rpc();
+
+    const setOverride = (oracle: PublicKey, secs: number | null) =>
+      program.methods
+        .setOracleMinUpdateInterval(tzsNgn, oracle, secs === null ? null : new anchor.BN(secs))
+        .accounts({ rateData: tzsNgnPDA, authority: authority })
+        .rpc();
+
+    const assertTooFrequent = async (oracle: anchor.web3.Keypair, rate: number) => {
+      try {
+        await updateRate(oracle, rate);
+        assert.fail("Should have failed for an update inside the minimum interval.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "UpdateTooFrequent");
+      }
+    };
+
+    before(async () => {
+      await createFeed(tzsNgn, [["Fast", fastOracle], ["Slow", slowOracle]]);
+      await program.methods
+        .setMinUpdateInterval(tzsNgn, new anchor.BN(60))
+        .accounts({ rateData: tzsNgnPDA, authority: authority })
+        .rpc();
+    });
+
+    it("Rejects a second update inside the feed's interval", async () => {
+      await updateRate(slowOracle, 30);
+      await assertTooFrequent(slowOracle, 31);
+    });
+
+    it("Lets a per-oracle override replace the feed's interval", async () => {
+      await setOverride(fastOracle.publicKey, 0);
+      await updateRate(fastOracle, 30);
+      await updateRate(fastOracle, 31);
+
+      const account = await program.account.rateData.fetch(tzsNgnPDA);
+      const [fast, slow] = activeOracles(account);
+      assert.equal(fast.hasIntervalOverride, 1);
+      assert.equal(slow.hasIntervalOverride, 0);
+      await assertTooFrequent(slowOracle, 31);
+    });
+
+    it("Falls back to the feed's interval once the override is cleared", async () => {
+      await setOverride(fastOracle.publicKey, null);
+      await assertTooFrequent(fastOracle, 32);
+    });
+
+    it("Rejects a negative interval", async () => {
+      try {
+        await setOverride(fastOracle.publicKey, -1);
+        assert.fail("Should have failed for a negative interval.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "InvalidUpdateInterval");
+      }
+    });
+  });
+
+  describe("weighting oracles", () => {
+    const xafNgn = { base: "XAF", quote: "NGN" };
+    const xafNgnPDA = findRateDataPDA(xafNgn);
+    const bankOracle = anchor.web3.Keypair.generate();
+    const p2pOracles = [anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate()];
+
+    const setMethod = (method: any) =>
+      program.methods
+        .setAggregationMethod(xafNgn, method)
+        .accounts({ rateData: xafNgnPDA, authority: authority })
+        .rpc();
+
+    const aggregate = async () =>
+      (await program.account.rateData.fetch(xafNgnPDA)).aggregateRate.toNumber();
+
+    before(async () => {
+      await createFeed(xafNgn, [["Bank", bankOracle], ["P2P 1", p2pOracles[0]], ["P2P 2", p2pOracles[1]]]);
+      for (const [oracle, rate] of [[bankOracle, 240], [p2pOracles[0], 250], [p2pOracles[1], 262]] as const) {
+        await program.methods
+          .updateRate(xafNgn, new anchor.BN(rate))
+          .accounts({
+            rateData: xafNgnPDA,
+            rateHistory: findRateHistoryPDA(xafNgnPDA),
+            oracle: oracle.publicKey,
+          })
+          .signers([oracle])
+          .rpc();
+      }
+    });
+
+    it("Gives new oracles a weight of 1 and keeps the plain median by default", async () => {
+      const account = await program.account.rateData.fetch(xafNgnPDA);
+      assert.deepEqual(activeOracles(account).map(o => o.weight), [1, 1, 1]);
+      assert.equal(account.aggregationMethod, 0);
+      assert.equal(account.aggregateRate.toNumber(), 250);
+    });
+
+    it("Lets a heavily weighted oracle dominate the weighted median", async () => {
+      await program.methods
+        .setOracleWeight(xafNgn, bankOracle.publicKey, 3)
+        .accounts({ rateData: xafNgnPDA, authority: authority })
+        .rpc();
+      // Weights don't affect the plain median.
+      assert.equal(await aggregate(), 250);
+
+      await setMethod({ weightedMedian: {} });
+      assert.equal(await aggregate(), 240);
+    });
+
+    it("Averages rates by weight under the weighted mean", async () => {
+      await setMethod({ weightedMean: {} });
+      // (240 * 3 + 250 + 262) / 5
+      assert.equal(await aggregate(), 246);
+    });
+
+    it("Switches to a trimmed mean or either edge of the band", async () => {
+      // 34% of three rates trims one from each end.
+      await setMethod({ trimmedMean: { trimPct: 34 } });
+      assert.equal(await aggregate(), 250);
+      const account = await program.account.rateData.fetch(xafNgnPDA);
+      assert.equal(account.trimPct, 34);
+
+      await setMethod({ min: {} });
+      assert.equal(await aggregate(), 240);
+      await setMethod({ max: {} });
+      assert.equal(await aggregate(), 262);
+
+      try {
+        await setMethod({ trimmedMean: { trimPct: 50 } });
+        assert.fail("Should have failed for trimming everything.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "InvalidTrimPercentage");
+      }
+    });
+
+    it("Only lets the authority change weights", async () => {
+      try {
+        await program.methods
+          .setOracleWeight(xafNgn, bankOracle.publicKey, 100)
+          .accounts({ rateData: xafNgnPDA, authority: unauthorizedUser.publicKey })
+          .signers([unauthorizedUser])
+          .rpc();
+        assert.fail("Should have failed for a non-authority signer.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ConstraintHasOne");
+      }
+    });
+  });
+
+  describe("staking and slashing oracles", () => {
+    const gbpNgn = { base: "GBP", quote: "NGN" };
+    const gbpNgnPDA = findRateDataPDA(gbpNgn);
+    const operator = anchor.web3.Keypair.generate();
+    const payer = (provider.wallet as anchor.Wallet).payer;
+    let mint: PublicKey;
+    let operatorTokens: PublicKey;
+    let treasury: PublicKey;
+
+    const findStakePDAs = (staker: PublicKey) => ({
+      oracleStake: PublicKey.findProgramAddressSync(
+        [Buffer.from("oracle_stake"), gbpNgnPDA.toBuffer(), staker.toBuffer()],
+        program.programId
+      )[0],
+      stakeVault: PublicKey.findProgramAddressSync(
+        [Buffer.from("stake_vault"), gbpNgnPDA.toBuffer(), staker.toBuffer()],
+        program.programId
+      )[0],
+    });
+
+    const stake = (amount: number) =>
+      program.methods
+        .stake(gbpNgn, new anchor.BN(amount))
+        .accounts({
+          rateData: gbpNgnPDA,
+          ...findStakePDAs(operator.publicKey),
+          stakeMint: mint,
+          stakerTokens: operatorTokens,
+          staker: operator.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([operator])
+        .rpc();
+
+    const withdraw = () =>
+      program.methods
+        .withdrawStake(gbpNgn)
+        .accounts({
+          rateData: gbpNgnPDA,
+          ...findStakePDAs(operator.publicKey),
+          stakeMint: mint,
+          stakerTokens: operatorTokens,
+          staker: operator.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([operator])
+        .rpc();
+
+    const updateRate = (rate: number) =>
+      program.methods
+        .updateRate(gbpNgn, new anchor.BN(rate))
+        .accounts({
+          rateData: gbpNgnPDA,
+          rateHistory: findRateHistoryPDA(gbpNgnPDA),
+          oracle: operator.publicKey,
+        })
+        .signers([operator])
+        .rpc();
+
+    const tokenBalance = async (account: PublicKey) =>
+      Number((await getAccount(provider.connection, account)).amount);
+
+    before(async () => {
+      await createFeed(gbpNgn, [["Staked Bank", operator]]);
+
+      // The operator pays for its stake accounts.
+      const sig = await provider.connection.requestAirdrop(operator.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+
+      mint = await createMint(provider.connection, payer, authority, null, 6);
+      operatorTokens = await createAccount(provider.connection, payer, mint, operator.publicKey);
+      treasury = await createAccount(provider.connection, payer, mint, authority);
+      await mintTo(provider.connection, payer, mint, operatorTokens, payer, 5000);
+    });
+
+    it("Refuses stake until staking is configured", async () => {
+      try {
+        await stake(1000);
+        assert.fail("Should have failed before staking was configured.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "StakingNotConfigured");
+      }
+
+      await program.methods
+        .configureStaking(gbpNgn, mint, treasury, new anchor.BN(1000), new anchor.BN(2))
+        .accounts({ rateData: gbpNgnPDA, authority: authority })
+        .rpc();
+    });
+
+    it("Only lets oracles with the minimum stake update", async () => {
+      try {
+        await updateRate(180000);
+        assert.fail("Should have failed without stake.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "InsufficientStake");
+      }
+
+      await stake(1000);
+      await updateRate(180000);
+
+      const account = await program.account.rateData.fetch(gbpNgnPDA);
+      assert.equal(activeOracles(account)[0].stake.toNumber(), 1000);
+      assert.equal(account.aggregateRate.toNumber(), 180000);
+      assert.equal(await tokenBalance(findStakePDAs(operator.publicKey).stakeVault), 1000);
+    });
+
+    it("Moves an approved slash to the treasury", async () => {
+      try {
+        await program.methods
+          .approveSlash(gbpNgn, operator.publicKey, new anchor.BN(400))
+          .accounts({
+            rateData: gbpNgnPDA,
+            oracleStake: findStakePDAs(operator.publicKey).oracleStake,
+            authority: unauthorizedUser.publicKey,
+          })
+          .signers([unauthorizedUser])
+          .rpc();
+        assert.fail("Should have failed for a non-authority signer.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ConstraintHasOne");
+      }
+
+      await program.methods
+        .approveSlash(gbpNgn, operator.publicKey, new anchor.BN(400))
+        .accounts({
+          rateData: gbpNgnPDA,
+          oracleStake: findStakePDAs(operator.publicKey).oracleStake,
+          authority: authority,
+        })
+        .rpc();
+      // Anyone can carry out an approved slash.
+      await program.methods
+        .slash(gbpNgn, operator.publicKey)
+        .accounts({
+          rateData: gbpNgnPDA,
+          ...findStakePDAs(operator.publicKey),
+          stakeMint: mint,
+          treasury,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      assert.equal(await tokenBalance(treasury), 400);
+      const account = await program.account.rateData.fetch(gbpNgnPDA);
+      assert.equal(activeOracles(account)[0].stake.toNumber(), 600);
+      // Below the minimum, the oracle's rate no longer counts.
+      assert.equal(account.numContributors, 0);
+
+      try {
+        await updateRate(180500);
+        assert.fail("Should have failed below the minimum stake.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "InsufficientStake");
+      }
+    });
+
+    it("Returns unstaked tokens only after the unbonding period", async () => {
+      await program.methods
+        .requestUnstake(gbpNgn, new anchor.BN(600))
+        .accounts({
+          rateData: gbpNgnPDA,
+          oracleStake: findStakePDAs(operator.publicKey).oracleStake,
+          staker: operator.publicKey,
+        })
+        .signers([operator])
+        .rpc();
+
+      try {
+        await withdraw();
+        assert.fail("Should have failed while unbonding.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "StakeStillUnbonding");
+      }
+
+      await sleep(3000);
+      await withdraw();
+      // 5000 minted, 1000 staked, 400 slashed.
+      assert.equal(await tokenBalance(operatorTokens), 4600);
+
+      const account = await program.account.rateData.fetch(gbpNgnPDA);
+      assert.equal(account.totalStaked.toNumber(), 0);
+    });
+
+    it("Picks up stake a key had before it became an oracle", async () => {
+      const earlyBank = anchor.web3.Keypair.generate();
+      const sig = await provider.connection.requestAirdrop(earlyBank.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+      const earlyBankTokens = await createAccount(provider.connection, payer, mint, earlyBank.publicKey);
+      await mintTo(provider.connection, payer, mint, earlyBankTokens, payer, 1000);
+
+      await program.methods
+        .stake(gbpNgn, new anchor.BN(1000))
+        .accounts({
+          rateData: gbpNgnPDA,
+          ...findStakePDAs(earlyBank.publicKey),
+          stakeMint: mint,
+          stakerTokens: earlyBankTokens,
+          staker: earlyBank.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([earlyBank])
+        .rpc();
+      await program.methods
+        .addOracle(gbpNgn, "Early Bank", earlyBank.publicKey)
+        .accounts({ rateData: gbpNgnPDA, authority: authority })
+        .rpc();
+
+      const earlyUpdate = () =>
+        program.methods
+          .updateRate(gbpNgn, new anchor.BN(181000))
+          .accounts({ rateData: gbpNgnPDA, rateHistory: findRateHistoryPDA(gbpNgnPDA), oracle: earlyBank.publicKey })
+          .signers([earlyBank])
+          .rpc();
+      try {
+        await earlyUpdate();
+        assert.fail("Should have failed before the stake was synced.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "InsufficientStake");
+      }
+
+      // Anyone can copy the key's stake onto its oracle.
+      const [event] = await eventsOf(
+        await program.methods
+          .syncStake(gbpNgn)
+          .accounts({ rateData: gbpNgnPDA, oracleStake: findStakePDAs(earlyBank.publicKey).oracleStake })
+          .rpc()
+      );
+      assert.equal(event.name, "stakeSynced");
+      assert.equal(event.data.bonded.toNumber(), 1000);
+      await earlyUpdate();
+
+      const account = await program.account.rateData.fetch(gbpNgnPDA);
+      assert.equal(activeOracles(account)[1].stake.toNumber(), 1000);
+      assert.equal(account.aggregateRate.toNumber(), 181000);
+
+      // A key rotated out of the feed still answers for the rates it signed.
+      await program.methods
+        .replaceOracleKey(gbpNgn, earlyBank.publicKey, anchor.web3.Keypair.generate().publicKey)
+        .accounts({ rateData: gbpNgnPDA, authority: authority })
+        .rpc();
+      await program.methods
+        .approveSlash(gbpNgn, earlyBank.publicKey, new anchor.BN(300))
+        .accounts({
+          rateData: gbpNgnPDA,
+          oracleStake: findStakePDAs(earlyBank.publicKey).oracleStake,
+          authority: authority,
+        })
+        .rpc();
+      await program.methods
+        .slash(gbpNgn, earlyBank.publicKey)
+        .accounts({
+          rateData: gbpNgnPDA,
+          ...findStakePDAs(earlyBank.publicKey),
+          stakeMint: mint,
+          treasury,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      assert.equal(await tokenBalance(treasury), 700);
+      const stake = await program.account.oracleStake.fetch(findStakePDAs(earlyBank.publicKey).oracleStake);
+      assert.equal(stake.amount.toNumber(), 700);
+      assert.equal(stake.slashApproved.toNumber(), 0);
+    });
+  });
+
+  describe("rewarding oracles", () => {
+    const cadNgn = { base: "CAD", quote: "NGN" };
+    const cadNgnPDA = findRateDataPDA(cadNgn);
+    const rewardVault = PublicKey.findProgramAddressSync(
+      [Buffer.from("reward_vault"), cadNgnPDA.toBuffer()],
+      program.programId
+    )[0];
+    const operator = anchor.web3.Keypair.generate();
+    const payer = (provider.wallet as anchor.Wallet).payer;
+    let mint: PublicKey;
+    let funderTokens: PublicKey;
+    let operatorTokens: PublicKey;
+
+    const fund = (amount: number) =>
+      program.methods
+        .fundRewards(cadNgn, new anchor.BN(amount))
+        .accounts({
+          rateData: cadNgnPDA,
+          rewardVault,
+          rewardMint: mint,
+          funderTokens,
+          funder: authority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+    const claim = () =>
+      program.methods
+        .claimRewards(cadNgn)
+        .accounts({
+          rateData: cadNgnPDA,
+          rewardVault,
+          rewardMint: mint,
+          oracleTokens: operatorTokens,
+          oracle: operator.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([operator])
+        .rpc();
+
+    const updateRate = (rate: number) =>
+      program.methods
+        .updateRate(cadNgn, new anchor.BN(rate))
+        .accounts({
+          rateData: cadNgnPDA,
+          rateHistory: findRateHistoryPDA(cadNgnPDA),
+          oracle: operator.publicKey,
+        })
+        .signers([operator])
+        .rpc();
+
+    const tokenBalance = async (account: PublicKey) =>
+      Number((await getAccount(provider.connection, account)).amount);
+
+    const removeOracle = () =>
+      program.methods
+        .removeOracle(cadNgn, operator.publicKey)
+        .accounts({ rateData: cadNgnPDA, authority: authority })
+        .rpc();
+
+    before(async () => {
+      await createFeed(cadNgn, [["Rewarded Bank", operator]]);
+
+      mint = await createMint(provider.connection, payer, authority, null, 6);
+      funderTokens = await createAccount(provider.connection, payer, mint, authority);
+      operatorTokens = await createAccount(provider.connection, payer, mint, operator.publicKey);
+      await mintTo(provider.connection, payer, mint, funderTokens, payer, 1000);
+    });
+
+    it("Refuses funding until rewards are configured", async () => {
+      try {
+        await fund(500);
+        assert.fail("Should have failed before rewards were configured.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "RewardsNotConfigured");
+      }
+
+      // 40 per update, at most 100 per oracle per epoch.
+      await program.methods
+        .configureRewards(cadNgn, mint, new anchor.BN(40), new anchor.BN(100))
+        .accounts({ rateData: cadNgnPDA, authority: authority })
+        .rpc();
+      await fund(500);
+      assert.equal(await tokenBalance(rewardVault), 500);
+    });
+
+    it("Locks the reward mint once it is set", async () => {
+      try {
+        await program.methods
+          .configureRewards(cadNgn, anchor.web3.Keypair.generate().publicKey, new anchor.BN(1), new anchor.BN(1))
+          .accounts({ rateData: cadNgnPDA, authority: authority })
+          .rpc();
+        assert.fail("Should have failed to change the reward mint.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "RewardMintLocked");
+      }
+    });
+
+    it("Accrues rewards per accepted update up to the epoch cap", async () => {
+      for (const rate of [120000, 120100, 120200, 120300]) {
+        await updateRate(rate);
+      }
+
+      const oracle = activeOracles(await program.account.rateData.fetch(cadNgnPDA))[0];
+      assert.equal(oracle.accruedRewards.toNumber(), 100);
+      assert.equal(oracle.epochRewards.toNumber(), 100);
+    });
+
+    it("Pays the oracle what it has accrued", async () => {
+      const signature = await claim();
+
+      assert.equal(await tokenBalance(operatorTokens), 100);
+      assert.equal(await tokenBalance(rewardVault), 400);
+      const oracle = activeOracles(await program.account.rateData.fetch(cadNgnPDA))[0];
+      assert.equal(oracle.accruedRewards.toNumber(), 0);
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "rewardsClaimed");
+      assert.equal(event.data.amount.toNumber(), 100);
+
+      try {
+        await claim();
+        assert.fail("Should have failed with nothing left to claim.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "NothingToClaim");
+      }
+    });
+
+    it("Forfeits what a removed oracle has not claimed", async () => {
+      // Raise the cap so the oracle can earn again this epoch.
+      await program.methods
+        .configureRewards(cadNgn, mint, new anchor.BN(40), new anchor.BN(1000))
+        .accounts({ rateData: cadNgnPDA, authority: authority })
+        .rpc();
+      await updateRate(120400);
+
+      const signature = await removeOracle();
+      const account = await program.account.rateData.fetch(cadNgnPDA);
+      assert.equal(account.numOracles, 0);
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "oracleRemoved");
+      assert.equal(event.data.forfeitedRewards.toNumber(), 40);
+      // The forfeited rewards stay in the vault.
+      assert.equal(await tokenBalance(rewardVault), 400);
+    });
+  });
+
+  describe("reading feeds over CPI", () => {
+    const consumer = anchor.workspace.RateConsumer as Program<RateConsumer>;
+    const jpyNgn = { base: "JPY", quote: "NGN" };
+    const jpyNgnPDA = findRateDataPDA(jpyNgn);
+    const bank = anchor.web3.Keypair.generate();
+    const exchange = anchor.web3.Keypair.generate();
+    const accounts = { rateData: jpyNgnPDA, trackerProgram: program.programId };
+
+    before(async () => {
+      await createFeed(jpyNgn, [["Bank", bank], ["Exchange", exchange]]);
+      await program.methods
+        .updateRate(jpyNgn, new anchor.BN(1020))
+        .accounts({ rateData: jpyNgnPDA, rateHistory: findRateHistoryPDA(jpyNgnPDA), oracle: bank.publicKey })
+        .signers([bank])
+        .rpc();
+    });
+
+    it("Returns the same quote the tracker does", async () => {
+      const direct = await program.methods.getRate(jpyNgn).accounts({ rateData: jpyNgnPDA }).view();
+      const viaCpi = await consumer.methods.readRate(jpyNgn).accounts(accounts).view();
+      assert.equal(viaCpi.rate.toNumber(), 1020);
+      assert.equal(viaCpi.rate.toNumber(), direct.rate.toNumber());
+      assert.equal(viaCpi.exponent, 2);
+      assert.equal(viaCpi.timestamp.toNumber(), direct.timestamp.toNumber());
+    });
+
+    it("Returns the aggregate with how it was formed", async () => {
+      const aggregate = await consumer.methods.readAggregate(jpyNgn).accounts(accounts).view();
+      assert.equal(aggregate.rate.toNumber(), 1020);
+      assert.equal(aggregate.numContributors, 1);
+      assert.equal(aggregate.numOracles, 2);
+      assert.deepEqual(aggregate.method, { median: {} });
+      // Never delegated, so never committed from a rollup.
+      assert.equal(aggregate.lastCommitTimestamp.toNumber(), 0);
+    });
+
+    it("Returns every oracle's latest rate", async () => {
+      const rates = await consumer.methods.readAllRates(jpyNgn).accounts(accounts).view();
+      assert.equal(rates.length, 2);
+      assert.isTrue(rates[0].pubkey.equals(bank.publicKey));
+      assert.equal(rates[0].rate.toNumber(), 1020);
+      assert.isTrue(rates[0].contributing);
+      // The exchange has not reported yet.
+      assert.equal(rates[1].rate.toNumber(), 0);
+      assert.isFalse(rates[1].contributing);
+    });
+
+    it("Reads a validated rate straight from the feed's account", async () => {
+      const checked = await consumer.methods.readCheckedRate(new anchor.BN(60), 1).accounts(accounts).view();
+      assert.equal(checked.rate.toNumber(), 1020);
+      assert.equal(checked.numContributors, 1);
+      assert.equal(checked.confidenceBps.toNumber(), 0);
+
+      try {
+        await consumer.methods.readCheckedRate(new anchor.BN(60), 2).accounts(accounts).rpc();
+        assert.fail("Should have failed with one contributing oracle.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "NotEnoughOracles");
+      }
+    });
+
+    it("Passes the tracker's checks through to the caller", async () => {
+      try {
+        // Another pair's feed is not this pair's PDA.
+        await consumer.methods
+          .readRate(jpyNgn)
+          .accounts({ rateData: rateDataPDA, trackerProgram: program.programId })
+          .rpc();
+        assert.fail("Should have failed for the wrong feed.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ConstraintSeeds");
+      }
+    });
+  });
+
+  describe("emitting events", () => {
+    const chfNgn = { base: "CHF", quote: "NGN" };
+    const chfNgnPDA = findRateDataPDA(chfNgn);
+    const oracle = anchor.web3.Keypair.generate();
+
+    const updateRate = (rate: number) =>
+      program.methods
+        .updateRate(chfNgn, new anchor.BN(rate))
+        .accounts({ rateData: chfNgnPDA, rateHistory: findRateHistoryPDA(chfNgnPDA), oracle: oracle.publicKey })
+        .signers([oracle])
+        .rpc();
+
+    it("Reports the feed's creation and new oracles", async () => {
+      const initialized = await program.methods
+        .initialize(chfNgn, 2)
+        .accounts({
+          rateData: chfNgnPDA,
+          rateHistory: findRateHistoryPDA(chfNgnPDA),
+          authority: authority,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      const [init] = await eventsOf(initialized);
+      assert.equal(init.name, "trackerInitialized");
+      assert.isTrue(init.data.rateData.equals(chfNgnPDA));
+      assert.equal(init.data.base, "CHF");
+      assert.equal(init.data.exponent, 2);
+
+      const added = await program.methods
+        .addOracle(chfNgn, "Swiss Bank", oracle.publicKey)
+        .accounts({ rateData: chfNgnPDA, authority: authority })
+        .rpc();
+      const [event] = await eventsOf(added);
+      assert.equal(event.name, "oracleAdded");
+      assert.isTrue(event.data.oracle.equals(oracle.publicKey));
+      assert.equal(event.data.name, "Swiss Bank");
+    });
+
+    it("Reports each update with its change from the previous rate", async () => {
+      const [first] = await eventsOf(await updateRate(180000));
+      assert.equal(first.name, "rateUpdated");
+      assert.equal(first.data.oldRate.toNumber(), 0);
+      assert.equal(first.data.changeBps.toNumber(), 0);
+
+      const [second] = await eventsOf(await updateRate(178200));
+      assert.equal(second.data.oldRate.toNumber(), 180000);
+      assert.equal(second.data.newRate.toNumber(), 178200);
+      assert.equal(second.data.changeBps.toNumber(), -100);
+      assert.equal(second.data.aggregateRate.toNumber(), 178200);
+      assert.equal(second.data.numContributors, 1);
+    });
+
+    it("Reports settings changes with the action applied", async () => {
+      const signature = await program.methods
+        .setMaxDeviation(chfNgn, 500)
+        .accounts({ rateData: chfNgnPDA, authority: authority })
+        .rpc();
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "settingsChanged");
+      assert.equal(event.data.action.setMaxDeviation.maxDeviationBps, 500);
+    });
+
+    it("Reports removed oracles", async () => {
+      const signature = await program.methods
+        .removeOracle(chfNgn, oracle.publicKey)
+        .accounts({ rateData: chfNgnPDA, authority: authority })
+        .rpc();
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "oracleRemoved");
+      assert.isTrue(event.data.oracle.equals(oracle.publicKey));
+    });
+  });
+
+  describe("delegating a feed to an ephemeral rollup", () => {
+    // The delegation instructions are behind the tracker's `delegation` feature,
+    // so they are missing from the default build and its generated types; see
+    // the README for building with it. The stand-ins in programs/delegation-stub
+    // and programs/magic-stub sit at the real programs' addresses.
+    const delegationBuilt = program.rawIdl.instructions.some((ix) => ix.name === "delegate_rate_data");
+    const tracker = program as unknown as Program<anchor.Idl>;
+    const delegationProgram = anchor.workspace.DelegationStub as Program<DelegationStub>;
+    const magicProgram = new PublicKey("Magic11111111111111111111111111111111111111");
+    const magicContext = new PublicKey("MagicContext1111111111111111111111111111111");
+
+    const eurNgn = { base: "EUR", quote: "NGN" };
+    const eurNgnPDA = findRateDataPDA(eurNgn);
+    const eurNgnHistoryPDA = findRateHistoryPDA(eurNgnPDA);
+    const oracle = anchor.web3.Keypair.generate();
+
+    // The delegation program's accounts are keyed by the delegated account.
+    const delegationPDA = (seed: string, account: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from(seed), account.toBuffer()], delegationProgram.programId)[0];
+    const bufferPDA = (account: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("buffer"), account.toBuffer()], program.programId)[0];
+
+    const updateRate = (rate: number) =>
+      program.methods
+        .updateRate(eurNgn, new anchor.BN(rate))
+        .accounts({ rateData: eurNgnPDA, rateHistory: eurNgnHistoryPDA, oracle: oracle.publicKey })
+        .signers([oracle])
+        .rpc();
+
+    const delegate = (signer: anchor.web3.Keypair | null) =>
+      tracker.methods
+        .delegateRateData(eurNgn, 30000, null)
+        .accounts({
+          rateData: eurNgnPDA,
+          rateHistory: eurNgnHistoryPDA,
+          rateDataBuffer: bufferPDA(eurNgnPDA),
+          rateHistoryBuffer: bufferPDA(eurNgnHistoryPDA),
+          rateDataDelegationRecord: delegationPDA("delegation", eurNgnPDA),
+          rateDataDelegationMetadata: delegationPDA("delegation-metadata", eurNgnPDA),
+          rateHistoryDelegationRecord: delegationPDA("delegation", eurNgnHistoryPDA),
+          rateHistoryDelegationMetadata: delegationPDA("delegation-metadata", eurNgnHistoryPDA),
+          authority: signer ? signer.publicKey : authority,
+          ownerProgram: program.programId,
+          delegationProgram: delegationProgram.programId,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers(signer ? [signer] : [])
+        .rpc();
+
+    // What the delegation program does once the rollup has committed an account
+    // for undelegation.
+    const undelegate = (account: PublicKey) =>
+      delegationProgram.methods
+        .undelegate()
+        .accounts({
+          payer: authority,
+          delegatedAccount: account,
+          ownerProgram: program.programId,
+          undelegateBuffer: delegationPDA("undelegate-buffer", account),
+          delegationRecord: delegationPDA("delegation", account),
+          delegationMetadata: delegationPDA("delegation-metadata", account),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+    const logsOf = async (signature: string) => {
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return tx.meta.logMessages;
+    };
+
+    let feedData: Buffer;
+    let historyData: Buffer;
+
+    before(async function () {
+      if (!delegationBuilt) {
+        this.skip();
+      }
+      await createFeed(eurNgn, [["ECB", oracle]]);
+      await updateRate(172050);
+      feedData = (await provider.connection.getAccountInfo(eurNgnPDA)).data;
+      historyData = (await provider.connection.getAccountInfo(eurNgnHistoryPDA)).data;
+    });
+
+    it("Only lets the feed's authority delegate it", async () => {
+      try {
+        await delegate(unauthorizedUser);
+        assert.fail("Should have failed for a non-authority signer.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ConstraintHasOne");
+      }
+    });
+
+    it("Hands the feed and its history to the delegation program", async () => {
+      const [event] = await eventsOf(await delegate(null));
+      assert.equal(event.name, "feedDelegated");
+      assert.isTrue(event.data.rateData.equals(eurNgnPDA));
+      assert.equal(event.data.commitFrequencyMs, 30000);
+      assert.isNull(event.data.validator);
+
+      for (const [account, data] of [[eurNgnPDA, feedData], [eurNgnHistoryPDA, historyData]] as const) {
+        const info = await provider.connection.getAccountInfo(account);
+        assert.isTrue(info.owner.equals(delegationProgram.programId));
+        assert.isTrue(info.data.equals(data));
+        const record = await delegationProgram.account.delegationRecord.fetch(delegationPDA("delegation", account));
+        assert.isTrue(record.owner.equals(program.programId));
+        assert.equal(record.commitFrequencyMs, 30000);
+        // The buffer only exists during the instruction.
+        assert.isNull(await provider.connection.getAccountInfo(bufferPDA(account)));
+      }
+      const metadata = await delegationProgram.account.delegationMetadata.fetch(
+        delegationPDA("delegation-metadata", eurNgnHistoryPDA)
+      );
+      assert.deepEqual(
+        metadata.seeds.map((seed) => Buffer.from(seed)),
+        [Buffer.from("rate_history"), eurNgnPDA.toBuffer()]
+      );
+    });
+
+    it("Locks the feed on the base layer while it is delegated", async () => {
+      try {
+        await updateRate(172100);
+        assert.fail("Should have failed while the feed is delegated.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "AccountOwnedByWrongProgram");
+      }
+    });
+
+    it("Only accepts undelegation from the delegation program", async () => {
+      const fakeBuffer = anchor.web3.Keypair.generate();
+      try {
+        await tracker.methods
+          .processUndelegation([Buffer.from("rate_data"), Buffer.from("EUR"), Buffer.from("/"), Buffer.from("NGN")])
+          .accounts({
+            delegatedAccount: eurNgnPDA,
+            buffer: fakeBuffer.publicKey,
+            payer: authority,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .signers([fakeBuffer])
+          .rpc();
+        assert.fail("Should have failed for a buffer the delegation program does not own.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ConstraintSeeds");
+      }
+    });
+
+    it("Takes the feed back with its data once undelegated", async () => {
+      const [event] = await eventsOf(await undelegate(eurNgnPDA));
+      assert.equal(event.name, "feedUndelegated");
+      assert.isTrue(event.data.rateData.equals(eurNgnPDA));
+      await undelegate(eurNgnHistoryPDA);
+
+      for (const [account, data] of [[eurNgnPDA, feedData], [eurNgnHistoryPDA, historyData]] as const) {
+        const info = await provider.connection.getAccountInfo(account);
+        assert.isTrue(info.owner.equals(program.programId));
+        assert.isTrue(info.data.equals(data));
+        assert.isNull(await provider.connection.getAccountInfo(delegationPDA("delegation", account)));
+      }
+
+      await updateRate(172100);
+      const rateData = await program.account.rateData.fetch(eurNgnPDA);
+      assert.equal(rateData.aggregateRate.toNumber(), 172100);
+    });
+
+    // On a rollup the feed is still owned by the tracker, so these run against
+    // an undelegated feed here.
+    it("Schedules a commit of the feed and its history with the magic program", async () => {
+      const signature = await tracker.methods
+        .commitRateData(eurNgn)
+        .accounts({
+          rateData: eurNgnPDA,
+          rateHistory: eurNgnHistoryPDA,
+          payer: authority,
+          magicContext,
+          magicProgram,
+        })
+        .rpc();
+      const logs = await logsOf(signature);
+      assert.include(logs, `Program log: Scheduled commit of ${eurNgnPDA.toBase58()}`);
+      assert.include(logs, `Program log: Scheduled commit of ${eurNgnHistoryPDA.toBase58()}`);
+    });
+
+    it("Only lets the feed's authority schedule its undelegation", async () => {
+      const undelegateRateData = (signer: anchor.web3.Keypair | null) =>
+        tracker.methods
+          .undelegateRateData(eurNgn)
+          .accounts({
+            rateData: eurNgnPDA,
+            rateHistory: eurNgnHistoryPDA,
+            authority: signer ? signer.publicKey : authority,
+            magicContext,
+            magicProgram,
+          })
+          .signers(signer ? [signer] : [])
+          .rpc();
+
+      try {
+        await undelegateRateData(unauthorizedUser);
+        assert.fail("Should have failed for a non-authority signer.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ConstraintHasOne");
+      }
+
+      const logs = await logsOf(await undelegateRateData(null));
+      assert.include(logs, `Program log: Scheduled commit and undelegation of ${eurNgnPDA.toBase58()}`);
+    });
+
+    it("Rejects a negative commit interval", async () => {
+      try {
+        await program.methods
+          .setCommitPolicy(eurNgn, new anchor.BN(-1), 0, 0)
+          .accounts({ rateData: eurNgnPDA, authority: authority })
+          .rpc();
+        assert.fail("Should have failed for a negative interval.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "InvalidCommitInterval");
+      }
+    });
+
+    it("Commits from update_rate once the feed's commit policy calls for it", async () => {
+      await program.methods
+        .setCommitPolicy(eurNgn, new anchor.BN(0), 2, 0)
+        .accounts({ rateData: eurNgnPDA, authority: authority })
+        .rpc();
+      const sig = await provider.connection.requestAirdrop(oracle.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+
+      // The oracle pays for the commit, so it sends the update itself.
+      const updateAndCommit = async (rate: number) => {
+        const tx = await program.methods
+          .updateRate(eurNgn, new anchor.BN(rate))
+          .accounts({ rateData: eurNgnPDA, rateHistory: eurNgnHistoryPDA, oracle: oracle.publicKey })
+          .remainingAccounts([
+            { pubkey: magicContext, isWritable: true, isSigner: false },
+            { pubkey: magicProgram, isWritable: false, isSigner: false },
+          ])
+          .transaction();
+        tx.feePayer = oracle.publicKey;
+        return anchor.web3.sendAndConfirmTransaction(provider.connection, tx, [oracle], {
+          commitment: "confirmed",
+        });
+      };
+
+      // The undelegation above was the last commit, so the second update is due.
+      const first = await updateAndCommit(172150);
+      assert.notInclude((await logsOf(first)).join("\n"), "Scheduled commit");
+      const second = await updateAndCommit(172200);
+      assert.include(await logsOf(second), `Program log: Scheduled commit of ${eurNgnPDA.toBase58()}`);
+
+      const [, event] = await eventsOf(second);
+      assert.equal(event.name, "commitScheduled");
+      assert.equal(event.data.aggregateRate.toNumber(), 172200);
+      const rateData = await program.account.rateData.fetch(eurNgnPDA);
+      assert.equal(rateData.updatesSinceCommit, 0);
+      assert.equal(rateData.lastCommitRate.toNumber(), 172200);
+      assert.isTrue(rateData.lastCommitTimestamp.eq(event.data.timestamp));
+    });
+  });
+
+  describe("migrating a legacy Borsh feed", () => {
+    // Preloaded by Anchor.toml from tests/fixtures/legacy-rate-data.json: a ZAR/NGN
+    // feed in the pre-zero-copy layout with "Bank A" at 80.00 and "P2P Market" at 81.50.
+    const zarNgn = { base: "ZAR", quote: "NGN" };
+    const legacyPDA = findRateDataPDA(zarNgn);
+    const legacyAuthority = anchor.web3.Keypair.fromSecretKey(
+      Uint8Array.from(JSON.parse(fs.readFileSync("tests/fixtures/legacy-authority.json", "utf8")))
+    );
+
+    const migrate = (signer: anchor.web3.Keypair) =>
+      program.methods
+        .migrateRateData(zarNgn)
+        .accounts({
+          rateData: legacyPDA,
+          authority: signer.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      // The authority pays for the larger zero-copy account.
+      const sig = await provider.connection.requestAirdrop(legacyAuthority.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    it("Only lets the feed's authority migrate it", async () => {
+      try {
+        await migrate(unauthorizedUser);
+        assert.fail("Should have failed for a non-authority signer.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ConstraintHasOne");
+      }
+    });
+
+    it("Migrates oracles, aggregate and settings to the zero-copy layout", async () => {
+      await migrate(legacyAuthority);
+
+      const account = await program.account.rateData.fetch(legacyPDA);
+      assert.ok(account.authority.equals(legacyAuthority.publicKey));
+      assert.equal(decodeFixed(account.base), "ZAR");
+      assert.equal(decodeFixed(account.quote), "NGN");
+      assert.equal(account.exponent, 2);
+      assert.equal(account.aggregateRate.toNumber(), 8075);
+      assert.equal(account.numContributors, 2);
+      assert.equal(account.maxStalenessSecs.toNumber(), 60);
+      assert.deepEqual(
+        activeOracles(account).map(o => [decodeFixed(o.name), o.rate.toNumber()]),
+        [["Bank A", 8000], ["P2P Market", 8150]]
+      );
+
+      // The account is exactly rent-exempt at its new size.
+      const info = await provider.connection.getAccountInfo(legacyPDA);
+      const rentExempt = await provider.connection.getMinimumBalanceForRentExemption(info.data.length);
+      assert.equal(info.lamports, rentExempt);
+    });
+
+    it("Refuses to migrate a feed twice", async () => {
+      try {
+        await migrate(legacyAuthority);
+        assert.fail("Should have failed for an already migrated feed.");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "AlreadyMigrated");
+      }
+    });
+  });
 });